@@ -1,27 +1,28 @@
 #![warn(clippy::all)]
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::os::unix::ffi::OsStrExt as _;
 use std::os::unix::io::RawFd;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 
 use futures_core::Stream;
 pub use input::event;
-use input::{Event, Libinput, LibinputInterface};
+use input::{Device, Event, Libinput, LibinputInterface};
 use log::{debug, warn};
 use tokio::io::unix::AsyncFd;
 
 pub use codes::{ButtonCodes, KeyCodes};
 
 pub mod model {
-    pub use input::{Device, DeviceCapability};
+    pub use input::{Device, DeviceCapability, Led, SendEventsMode};
 }
 mod codes;
 mod sys;
+mod watch;
 
 macro_rules! ready {
     ($e:expr) => {
@@ -47,23 +48,51 @@ fn grab(fd: RawFd, grab: bool) -> io::Result<()> {
     }
 }
 
+fn rdev(fd: RawFd) -> io::Result<libc::dev_t> {
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    match unsafe { libc::fstat(fd, stat.as_mut_ptr()) } {
+        err if err < 0 => Err(io::Error::last_os_error()),
+        _ => Ok(unsafe { stat.assume_init() }.st_rdev),
+    }
+}
+
 #[derive(Debug, Default)]
 struct GrabCollection {
     grabbed: bool,
-    fds: HashSet<RawFd>,
+    /// fd -> device number
+    fds: HashMap<RawFd, libc::dev_t>,
+    /// Device numbers never grabbed.
+    excluded: HashSet<libc::dev_t>,
 }
 
 impl GrabCollection {
     fn add(&mut self, fd: RawFd) -> io::Result<()> {
-        if self.fds.insert(fd) && self.grabbed {
+        let dev = rdev(fd)?;
+        if self.fds.insert(fd, dev).is_none() && self.grabbed && !self.excluded.contains(&dev) {
             grab(fd, true)?;
         }
         Ok(())
     }
 
     fn remove(&mut self, fd: RawFd) -> io::Result<()> {
-        if self.fds.remove(&fd) && self.grabbed {
-            grab(fd, false)?;
+        if let Some(dev) = self.fds.remove(&fd) {
+            if self.grabbed && !self.excluded.contains(&dev) {
+                grab(fd, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_excluded(&mut self, dev: libc::dev_t, excluded: bool) -> io::Result<()> {
+        let changed = if excluded {
+            self.excluded.insert(dev)
+        } else {
+            self.excluded.remove(&dev)
+        };
+        if changed && self.grabbed {
+            for (fd, _) in self.fds.iter().filter(|(_, d)| **d == dev) {
+                grab(*fd, !excluded)?;
+            }
         }
         Ok(())
     }
@@ -71,8 +100,10 @@ impl GrabCollection {
     fn grab(&mut self) -> io::Result<()> {
         if !self.grabbed {
             self.grabbed = true;
-            for fd in &self.fds {
-                grab(*fd, true)?;
+            for (fd, dev) in &self.fds {
+                if !self.excluded.contains(dev) {
+                    grab(*fd, true)?;
+                }
             }
         }
         Ok(())
@@ -81,8 +112,10 @@ impl GrabCollection {
     fn ungrab(&mut self) -> io::Result<()> {
         if self.grabbed {
             self.grabbed = false;
-            for fd in &self.fds {
-                grab(*fd, false)?;
+            for (fd, dev) in &self.fds {
+                if !self.excluded.contains(dev) {
+                    grab(*fd, false)?;
+                }
             }
         }
         Ok(())
@@ -139,6 +172,9 @@ impl LibinputInterface for Env {
 pub struct LibinputStream {
     grabs: Arc<Mutex<GrabCollection>>,
     io: AsyncFd<Libinput>,
+    /// Devices added by path. (path backend only)
+    devices: HashMap<PathBuf, Device>,
+    watches: Vec<watch::Watch>,
 }
 
 impl LibinputStream {
@@ -150,9 +186,53 @@ impl LibinputStream {
         Ok(LibinputStream {
             grabs,
             io: AsyncFd::new(libinput)?,
+            devices: Default::default(),
+            watches: vec![],
         })
     }
 
+    /// Path backend. Add devices by [`LibinputStream::add_device`] or [`LibinputStream::watch`].
+    pub fn new_from_path() -> io::Result<LibinputStream> {
+        let grabs = Arc::new(Mutex::new(Default::default()));
+        let libinput = Libinput::new_from_path(Env(grabs.clone()));
+        Ok(LibinputStream {
+            grabs,
+            io: AsyncFd::new(libinput)?,
+            devices: Default::default(),
+            watches: vec![],
+        })
+    }
+
+    /// Add a device file. (e.g. /dev/input/event3) Path backend only.
+    pub fn add_device<P>(&mut self, path: P) -> io::Result<Device>
+    where
+        P: AsRef<Path>,
+    {
+        add_device(self.io.get_mut(), &mut self.devices, path.as_ref())
+    }
+
+    /// Remove a device added by [`LibinputStream::add_device`]. Path backend only.
+    pub fn remove_device(&mut self, device: Device) {
+        self.devices.retain(|_, d| d != &device);
+        self.io.get_mut().path_remove_device(device);
+    }
+
+    /// Add event devices in the directory and follow additions and removals. (e.g. /dev/input)
+    /// Path backend only.
+    pub fn watch<P>(&mut self, dir: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let watch = watch::Watch::new(dir)?;
+        for path in watch.existing()? {
+            if let Err(err) = self.add_device(&path) {
+                debug!("failed to add {:?}: {}", path, err);
+            }
+        }
+        self.watches.push(watch);
+        Ok(())
+    }
+
     pub fn grab(&mut self) -> io::Result<()> {
         match self.grabs.lock() {
             Ok(mut grabs) => grabs.grab(),
@@ -166,13 +246,71 @@ impl LibinputStream {
             Err(..) => Err(io::Error::new(io::ErrorKind::Other, "failed to lock")),
         }
     }
+
+    /// Exclude the device from [`LibinputStream::grab`] if `grab` is false. (default: true)
+    pub fn set_device_grab(&mut self, device: &Device, grab: bool) -> io::Result<()> {
+        // udev device is only used while the libinput device is alive.
+        let dev = unsafe { device.udev_device() }
+            .and_then(|udev| udev.devnum())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no device number"))?;
+        match self.grabs.lock() {
+            Ok(mut grabs) => grabs.set_excluded(dev, !grab),
+            Err(..) => Err(io::Error::other("failed to lock")),
+        }
+    }
+}
+
+fn add_device(
+    libinput: &mut Libinput,
+    devices: &mut HashMap<PathBuf, Device>,
+    path: &Path,
+) -> io::Result<Device> {
+    if let Some(device) = devices.get(path) {
+        return Ok(device.clone());
+    }
+
+    let spath = path
+        .to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", path)))?;
+    if let Some(device) = libinput.path_add_device(spath) {
+        devices.insert(path.to_path_buf(), device.clone());
+        Ok(device)
+    } else {
+        Err(io::Error::other(format!("failed to add device {:?}", path)))
+    }
 }
 
 impl Stream for LibinputStream {
     type Item = Result<Event, io::Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let Self { io, .. } = self.get_mut();
+        let Self {
+            io,
+            devices,
+            watches,
+            ..
+        } = self.get_mut();
+
+        for watch in watches.iter() {
+            while let Poll::Ready(changes) = watch.poll_changes(cx) {
+                for change in changes? {
+                    match change {
+                        watch::Change::Added(path) => {
+                            // may be not permitted yet. retry on attribute change.
+                            if let Err(err) = add_device(io.get_mut(), devices, &path) {
+                                debug!("{}", err);
+                            }
+                        }
+                        watch::Change::Removed(path) => {
+                            if let Some(device) = devices.remove(&path) {
+                                io.get_mut().path_remove_device(device);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
         loop {
             if let Some(event) = io.get_mut().next() {
                 return Poll::Ready(Some(Ok(event)));
@@ -0,0 +1,157 @@
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt as _;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::task::{Context, Poll};
+
+use tokio::io::unix::AsyncFd;
+
+#[derive(Debug)]
+struct Inotify(RawFd);
+
+impl AsRawFd for Inotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Change {
+    /// Created or attributes changed. (e.g. permission fixed by udev)
+    Added(PathBuf),
+    Removed(PathBuf),
+}
+
+/// Watch event device nodes (`event*`) in a directory with inotify.
+#[derive(Debug)]
+pub(crate) struct Watch {
+    dir: PathBuf,
+    fd: AsyncFd<Inotify>,
+}
+
+impl Watch {
+    pub(crate) fn new<P>(dir: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        let fd = match unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) } {
+            err if err < 0 => return Err(io::Error::last_os_error()),
+            fd => Inotify(fd),
+        };
+
+        let cpath = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mask = libc::IN_CREATE | libc::IN_ATTRIB | libc::IN_DELETE | libc::IN_MOVED_FROM;
+        if unsafe { libc::inotify_add_watch(fd.0, cpath.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            dir,
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Event device nodes currently in the directory.
+    pub(crate) fn existing(&self) -> io::Result<Vec<PathBuf>> {
+        let mut result = vec![];
+        for entry in self.dir.read_dir()? {
+            let entry = entry?;
+            if is_event_device(&entry.file_name()) {
+                result.push(entry.path());
+            }
+        }
+        result.sort();
+        Ok(result)
+    }
+
+    pub(crate) fn poll_changes(&self, cx: &mut Context<'_>) -> Poll<io::Result<Vec<Change>>> {
+        loop {
+            let mut guard = match self.fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let mut buf = [0u8; 4096];
+            match guard.try_io(|fd| {
+                match unsafe { libc::read(fd.get_ref().0, buf.as_mut_ptr() as *mut _, buf.len()) } {
+                    err if err < 0 => Err(io::Error::last_os_error()),
+                    n => Ok(n as usize),
+                }
+            }) {
+                Ok(Ok(n)) => return Poll::Ready(Ok(self.parse(&buf[..n]))),
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(..) => continue,
+            }
+        }
+    }
+
+    fn parse(&self, mut buf: &[u8]) -> Vec<Change> {
+        const HEADER_LEN: usize = mem::size_of::<libc::inotify_event>();
+
+        let mut result = vec![];
+        while buf.len() >= HEADER_LEN {
+            let event = unsafe { (buf.as_ptr() as *const libc::inotify_event).read_unaligned() };
+            let end = (HEADER_LEN + event.len as usize).min(buf.len());
+            let name = &buf[HEADER_LEN..end];
+            let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+            let name = OsStr::from_bytes(name);
+            buf = &buf[end..];
+
+            if !is_event_device(name) {
+                continue;
+            }
+            let path = self.dir.join(name);
+            if event.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+                result.push(Change::Removed(path));
+            } else {
+                result.push(Change::Added(path));
+            }
+        }
+        result
+    }
+}
+
+fn is_event_device(name: &OsStr) -> bool {
+    name.as_bytes().starts_with(b"event")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watch() {
+        let dir =
+            std::env::temp_dir().join(format!("btknmle-input-watch-test-{}", std::process::id()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("event0"), b"").unwrap();
+
+        let watch = Watch::new(&dir).unwrap();
+        assert_eq!(watch.existing().unwrap(), vec![dir.join("event0")]);
+
+        std::fs::write(dir.join("mouse0"), b"").unwrap();
+        std::fs::write(dir.join("event1"), b"").unwrap();
+        std::fs::remove_file(dir.join("event0")).unwrap();
+
+        let changes = futures::future::poll_fn(|cx| watch.poll_changes(cx))
+            .await
+            .unwrap();
+        assert!(changes.contains(&Change::Added(dir.join("event1"))));
+        assert!(changes.contains(&Change::Removed(dir.join("event0"))));
+        assert!(!changes
+            .iter()
+            .any(|c| c == &Change::Added(dir.join("mouse0"))));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
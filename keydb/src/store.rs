@@ -1,10 +1,12 @@
 use std::collections::VecDeque;
 use std::mem;
-use std::path::Path;
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::os::unix::io::AsRawFd as _;
+use std::path::{Path, PathBuf};
 
-use btmgmt::packet::{IdentityResolvingKey, LongTermKey};
-use tokio::fs::{File, OpenOptions};
-use tokio::io::{self, AsyncSeekExt, AsyncWriteExt, BufStream, SeekFrom};
+use btmgmt::packet::{Address, IdentityResolvingKey, LongTermKey};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{self, AsyncWriteExt};
 
 use crate::serde::Wrapper;
 
@@ -27,6 +29,23 @@ impl Data {
     }
 }
 
+/// Bonds in portable format. (without the local key)
+#[derive(Debug, serde::Serialize)]
+struct ExportRef<'a> {
+    #[serde(skip_serializing_if = "VecDeque::is_empty")]
+    irks: &'a VecDeque<Wrapper<IdentityResolvingKey>>,
+    #[serde(skip_serializing_if = "VecDeque::is_empty")]
+    ltks: &'a VecDeque<Wrapper<LongTermKey>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Export {
+    #[serde(default)]
+    irks: Vec<Wrapper<IdentityResolvingKey>>,
+    #[serde(default)]
+    ltks: Vec<Wrapper<LongTermKey>>,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
@@ -37,51 +56,171 @@ pub enum Error {
 
     #[error(transparent)]
     Deserialize(#[from] toml::de::Error),
+
+    #[error("{0} is locked by another process")]
+    Locked(PathBuf),
+
+    #[error("store is opened as read only")]
+    ReadOnly,
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Advisory lock held while the store is opened for writing.
+fn lock(path: &Path) -> Result<std::fs::File, Error> {
+    let lock_path = with_suffix(path, ".lock");
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(&lock_path)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            return Err(Error::Locked(path.into()));
+        }
+        return Err(err.into());
+    }
+    Ok(file)
+}
+
+/// Returns None if the file is missing or empty.
+async fn read(path: &Path) -> Result<Option<Data>, Error> {
+    let buf = match fs::read(path).await {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(toml::from_slice(&buf)?))
+}
+
+/// Read the store. Fall back to the backup if the file is missing, empty or broken.
+///
+/// Returns whether the data is read from `path` (not from the backup) as well.
+async fn read_or_backup(path: &Path) -> Result<Option<(Data, bool)>, Error> {
+    let backup = with_suffix(path, ".bak");
+    let result = read(path).await;
+    if let Ok(Some(data)) = result {
+        return Ok(Some((data, true)));
+    }
+
+    match read(&backup).await {
+        Ok(Some(data)) => {
+            match &result {
+                Err(err) => log::warn!("failed to read {}: {}", path.display(), err),
+                Ok(..) => log::warn!("{} is missing or empty.", path.display()),
+            }
+            log::warn!("using backup {}", backup.display());
+            Ok(Some((data, false)))
+        }
+        _ => result.map(|data| data.map(|data| (data, true))),
+    }
 }
 
 #[derive(Debug)]
 pub struct Store {
-    file: BufStream<File>,
+    path: PathBuf,
+    lock: Option<std::fs::File>,
     data: Data,
+    /// The file at `path` is read or written successfully. Only it replaces the backup.
+    valid: bool,
 }
 
 impl Store {
+    /// Open the store for reading and writing.
+    ///
+    /// Fails with [`Error::Locked`] if another process opens the same store.
     pub async fn open<P>(path: P) -> Result<Self, Error>
     where
         P: AsRef<Path>,
     {
         let path = path.as_ref();
+        let lock = lock(path)?;
 
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .mode(0o600)
-            .open(path)
-            .await?;
-        let mut file = BufStream::new(file);
-        let mut buf = vec![];
-        io::copy(&mut file, &mut buf).await?;
-
-        if buf.is_empty() {
-            let data = Data::new();
-            let mut result = Self { file, data };
+        if let Some((data, valid)) = read_or_backup(path).await? {
+            Ok(Self {
+                path: path.into(),
+                lock: Some(lock),
+                data,
+                valid,
+            })
+        } else {
+            let mut result = Self {
+                path: path.into(),
+                lock: Some(lock),
+                data: Data::new(),
+                valid: false,
+            };
             result.dump().await?;
             Ok(result)
-        } else {
-            let data = toml::from_slice(&buf)?;
-            Ok(Self { file, data })
         }
     }
 
+    /// Open the store without lock. Any modification fails with [`Error::ReadOnly`].
+    pub async fn open_readonly<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let (data, valid) = read_or_backup(path)
+            .await?
+            .unwrap_or_else(|| (Data::new(), false));
+        Ok(Self {
+            path: path.into(),
+            lock: None,
+            data,
+            valid,
+        })
+    }
+
+    /// Write to a temporary file and replace with it.
+    ///
+    /// The previous file is kept as the backup unless it was broken.
     async fn dump(&mut self) -> Result<(), Error> {
+        if self.lock.is_none() {
+            return Err(Error::ReadOnly);
+        }
+
         let buf = toml::to_vec(&self.data)?;
 
-        let file = &mut self.file;
-        file.get_mut().set_len(0).await?;
-        file.get_mut().seek(SeekFrom::Start(0)).await?;
+        let tmp = with_suffix(&self.path, ".tmp");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)
+            .await?;
         file.write_all(&buf).await?;
-        file.flush().await?;
+        file.sync_all().await?;
+        drop(file);
+
+        // keep the previous one as the backup. the path never goes missing.
+        // the broken one is not, to keep the backup read instead.
+        if self.valid && fs::metadata(&self.path).await.is_ok() {
+            let bak = with_suffix(&self.path, ".bak");
+            match fs::remove_file(&bak).await {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+            fs::hard_link(&self.path, &bak).await?;
+        }
+        fs::rename(&tmp, &self.path).await?;
+        self.valid = true;
+
+        let dir = match self.path.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir).await?.sync_all().await?;
         Ok(())
     }
 
@@ -115,6 +254,56 @@ impl Store {
         Ok(())
     }
 
+    /// Remove all keys for the identity address.
+    ///
+    /// Returns false if no keys found.
+    pub async fn remove(&mut self, addr: &Address) -> Result<bool, Error> {
+        let before = self.data.irks.len() + self.data.ltks.len();
+        self.data.irks.retain(|k| &k.as_ref().address() != addr);
+        self.data.ltks.retain(|k| &k.as_ref().address() != addr);
+        if before == self.data.irks.len() + self.data.ltks.len() {
+            return Ok(false);
+        }
+        self.dump().await?;
+        Ok(true)
+    }
+
+    /// Remove all bonds. The local key is kept.
+    pub async fn clear(&mut self) -> Result<(), Error> {
+        self.data.irks.clear();
+        self.data.ltks.clear();
+        self.dump().await?;
+        Ok(())
+    }
+
+    /// Export bonds in a portable format.
+    pub fn export(&self) -> Result<String, Error> {
+        let export = ExportRef {
+            irks: &self.data.irks,
+            ltks: &self.data.ltks,
+        };
+        Ok(toml::to_string(&export)?)
+    }
+
+    /// Import bonds exported by [`Store::export`].
+    ///
+    /// Existing keys for the same address are replaced.
+    pub async fn import(&mut self, buf: &str) -> Result<(), Error> {
+        let Export { irks, ltks } = toml::from_str(buf)?;
+        for irk in irks.into_iter().rev() {
+            let addr = irk.as_ref().address();
+            self.data.irks.retain(|k| k.as_ref().address() != addr);
+            self.data.irks.push_front(irk);
+        }
+        for ltk in ltks.into_iter().rev() {
+            let addr = ltk.as_ref().address();
+            self.data.ltks.retain(|k| k.as_ref().address() != addr);
+            self.data.ltks.push_front(ltk);
+        }
+        self.dump().await?;
+        Ok(())
+    }
+
     pub fn iter_irks(&self) -> impl Iterator<Item = &'_ IdentityResolvingKey> {
         self.data.irks.iter().map(AsRef::as_ref)
     }
@@ -202,4 +391,83 @@ mod tests {
             assert_eq!(ltk.value(), &v1);
         }
     }
+
+    #[tokio::test]
+    async fn test_remove_export_import() {
+        let tmp = mktemp::TempFile::new("", "").unwrap();
+        let mut store = Store::open(tmp.path()).await.unwrap();
+
+        let a1 = Address::le_public_from_str("00:11:22:33:44:55").unwrap();
+        let a2 = Address::le_public_from_str("55:44:33:22:11:00").unwrap();
+        for addr in [&a1, &a2] {
+            store
+                .add_irk(IdentityResolvingKey::new(addr.clone(), rand::random()))
+                .await
+                .unwrap();
+        }
+
+        assert!(store.remove(&a1).await.unwrap());
+        assert!(!store.remove(&a1).await.unwrap());
+        assert_eq!(
+            store.iter_irks().map(|k| k.address()).collect::<Vec<_>>(),
+            vec![a2.clone()]
+        );
+
+        let exported = store.export().unwrap();
+        let k = store.key_for_resolvable_private_address().to_vec();
+        store.clear().await.unwrap();
+        assert_eq!(store.iter_irks().count(), 0);
+
+        store.import(&exported).await.unwrap();
+        drop(store);
+
+        let store = Store::open(tmp.path()).await.unwrap();
+        assert_eq!(k, store.key_for_resolvable_private_address());
+        assert_eq!(
+            store.iter_irks().map(|k| k.address()).collect::<Vec<_>>(),
+            vec![a2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lock_and_backup() {
+        let dir = mktemp::TempDir::new("").unwrap();
+        let path = Path::new(dir.path()).join("db.toml");
+
+        let mut store = Store::open(&path).await.unwrap();
+        assert!(matches!(Store::open(&path).await, Err(Error::Locked(..))));
+
+        let k = store.key_for_resolvable_private_address().to_vec();
+        store
+            .add_irk(IdentityResolvingKey::new(
+                Address::le_public_from_str("00:11:22:33:44:55").unwrap(),
+                rand::random(),
+            ))
+            .await
+            .unwrap();
+        drop(store);
+        assert!(with_suffix(&path, ".bak").exists());
+
+        // broken by power loss.
+        std::fs::write(&path, b"key_for_resolvable").unwrap();
+
+        let mut store = Store::open(&path).await.unwrap();
+        assert_eq!(k, store.key_for_resolvable_private_address());
+
+        // the broken one never replaces the backup.
+        store
+            .add_irk(IdentityResolvingKey::new(
+                Address::le_public_from_str("55:44:33:22:11:00").unwrap(),
+                rand::random(),
+            ))
+            .await
+            .unwrap();
+        let backup = read(&with_suffix(&path, ".bak")).await.unwrap().unwrap();
+        assert_eq!(k, backup.key_for_resolvable_private_address.as_ref());
+
+        // the written one does.
+        store.clear().await.unwrap();
+        let backup = read(&with_suffix(&path, ".bak")).await.unwrap().unwrap();
+        assert_eq!(backup.irks.len(), 1);
+    }
 }
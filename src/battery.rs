@@ -0,0 +1,84 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::config::{Battery, BatteryProvider};
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+fn read_attr(dir: &Path, name: &str) -> io::Result<String> {
+    Ok(fs::read_to_string(dir.join(name))?.trim().to_string())
+}
+
+fn parse_capacity(v: &str) -> io::Result<u8> {
+    let v = v
+        .trim()
+        .parse::<u8>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(v.min(100))
+}
+
+/// Capacities of the power supplies with type Battery.
+///
+/// `device_scope` selects the batteries of the peripherals (e.g. wireless keyboards)
+/// instead of the batteries powering the system.
+fn power_supply_capacities(device_scope: bool) -> io::Result<Vec<u8>> {
+    let mut result = vec![];
+    for entry in fs::read_dir(POWER_SUPPLY)? {
+        let dir = entry?.path();
+        if read_attr(&dir, "type").ok().as_deref() != Some("Battery") {
+            continue;
+        }
+        let is_device = read_attr(&dir, "scope").ok().as_deref() == Some("Device");
+        if is_device != device_scope {
+            continue;
+        }
+        match read_attr(&dir, "capacity").and_then(|v| parse_capacity(&v)) {
+            Ok(capacity) => result.push(capacity),
+            Err(err) => log::debug!("failed to read capacity of {}: {}", dir.display(), err),
+        }
+    }
+    Ok(result)
+}
+
+/// Returns None if no battery found.
+fn read_level(config: &Battery) -> io::Result<Option<u8>> {
+    match config.provider {
+        BatteryProvider::Fixed => Ok(Some(config.level.min(100))),
+        BatteryProvider::Sysfs => {
+            if let Some(path) = &config.path {
+                Ok(Some(parse_capacity(&fs::read_to_string(path)?)?))
+            } else {
+                Ok(power_supply_capacities(false)?.first().copied())
+            }
+        }
+        // report the lowest one.
+        BatteryProvider::Device => Ok(power_supply_capacities(true)?.into_iter().min()),
+    }
+}
+
+/// Poll the battery level and publish changes.
+pub(crate) async fn watch(config: Battery, tx: watch::Sender<u8>) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval.max(1)));
+    loop {
+        interval.tick().await;
+
+        match read_level(&config) {
+            Ok(Some(level)) => {
+                if *tx.borrow() != level {
+                    log::debug!("battery level: {}", level);
+                    tx.send(level).ok();
+                }
+            }
+            Ok(None) => log::debug!("no battery found."),
+            Err(err) => log::warn!("failed to read battery level: {}", err),
+        }
+
+        if config.provider == BatteryProvider::Fixed {
+            return Ok(());
+        }
+    }
+}
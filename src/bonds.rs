@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read as _, Write as _};
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::path::{Path, PathBuf};
+
+use bdaddr::Address;
+use btknmle_keydb::{Error as KeydbError, Store};
+use btmgmt::client::Client as MgmtClient;
+use btmgmt::packet::command as cmd;
+
+use crate::control::{self, Request};
+use crate::Controller;
+
+/// Manage bonded hosts.
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// List bonded hosts.
+    List,
+
+    /// Forget a bonded host. (through the daemon if running)
+    Remove {
+        /// Identity address of the host. (e.g. 00:11:22:33:44:55)
+        address: String,
+    },
+
+    /// Forget all bonded hosts. (through the daemon if running)
+    Clear,
+
+    /// Export bonds. (to stdout if FILE omitted)
+    Export { file: Option<PathBuf> },
+
+    /// Import bonds exported by `export`. (from stdin if FILE omitted)
+    ///
+    /// The daemon must be stopped.
+    Import { file: Option<PathBuf> },
+}
+
+/// Identity addresses known by the store.
+pub(crate) fn addresses(store: &Store) -> Vec<Address> {
+    let mut addrs = vec![];
+    for addr in store
+        .iter_ltks()
+        .map(|k| k.address())
+        .chain(store.iter_irks().map(|k| k.address()))
+    {
+        if !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    addrs
+}
+
+/// Tell the controller to forget the host.
+async fn unpair(controller: &Controller, addrs: Vec<Address>) {
+    let client = match MgmtClient::open() {
+        Ok(client) => client,
+        Err(err) => {
+            log::warn!("failed to open mgmt socket: {}", err);
+            return;
+        }
+    };
+    let device_id = match controller.find(&client).await {
+        Ok(Some(device_id)) => device_id,
+        Ok(None) => {
+            log::warn!("controller {} not found.", controller);
+            return;
+        }
+        Err(err) => {
+            log::warn!("failed to find controller {}: {}", controller, err);
+            return;
+        }
+    };
+
+    for addr in addrs {
+        if let Err(err) = client
+            .call(device_id, cmd::UnpairDevice::new(addr.clone(), true))
+            .await
+        {
+            log::debug!("failed to unpair {}: {}", addr, err);
+        }
+    }
+}
+
+/// Forget the bonds through the running daemon holding the store.
+async fn forget_by_daemon(control_socket: &Path, addrs: Vec<Address>) -> anyhow::Result<()> {
+    for addr in addrs {
+        let request = Request::ForgetBond {
+            address: addr.to_string(),
+        };
+        control::request(control_socket, &request)
+            .await
+            .map_err(|err| anyhow::anyhow!("daemon failed to forget {}: {}", addr, err))?;
+        log::info!("removed {}", addr);
+    }
+    Ok(())
+}
+
+/// Open the store for writing.
+///
+/// Returns None if the daemon holds the store.
+async fn open(var_file: &Path) -> anyhow::Result<Option<Store>> {
+    match Store::open(var_file).await {
+        Ok(store) => Ok(Some(store)),
+        Err(KeydbError::Locked(..)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Removing the bonds while the daemon holds the store goes through its control socket.
+pub async fn run(
+    var_file: PathBuf,
+    controller: Controller,
+    control_socket: PathBuf,
+    command: Command,
+) -> anyhow::Result<()> {
+    match command {
+        Command::List => {
+            let store = Store::open_readonly(var_file).await?;
+            let mut bonds = BTreeMap::<String, Vec<&str>>::new();
+            for ltk in store.iter_ltks() {
+                let kind = match ltk.key_type() {
+                    btmgmt::packet::LongTermKeyType::AuthenticatedKey
+                    | btmgmt::packet::LongTermKeyType::AuthenticatedP256Key => "ltk(authenticated)",
+                    _ => "ltk",
+                };
+                bonds
+                    .entry(ltk.address().to_string())
+                    .or_default()
+                    .push(kind);
+            }
+            for irk in store.iter_irks() {
+                bonds
+                    .entry(irk.address().to_string())
+                    .or_default()
+                    .push("irk");
+            }
+            for (addr, keys) in bonds {
+                println!("{}\t{}", addr, keys.join(","));
+            }
+        }
+
+        Command::Remove { address } => {
+            let store = open(&var_file).await?;
+            let addrs = if let Some(store) = &store {
+                addresses(store)
+            } else {
+                addresses(&Store::open_readonly(&var_file).await?)
+            };
+            let addrs = addrs
+                .into_iter()
+                .filter(|addr| addr.to_string().eq_ignore_ascii_case(&address))
+                .collect::<Vec<_>>();
+            if addrs.is_empty() {
+                anyhow::bail!("no bond found for {}", address);
+            }
+
+            if let Some(mut store) = store {
+                for addr in &addrs {
+                    store.remove(addr).await?;
+                    log::info!("removed {}", addr);
+                }
+                unpair(&controller, addrs).await;
+            } else {
+                forget_by_daemon(&control_socket, addrs).await?;
+            }
+        }
+
+        Command::Clear => {
+            if let Some(mut store) = open(&var_file).await? {
+                let addrs = addresses(&store);
+                store.clear().await?;
+                log::info!("removed {} hosts.", addrs.len());
+                unpair(&controller, addrs).await;
+            } else {
+                let addrs = addresses(&Store::open_readonly(&var_file).await?);
+                forget_by_daemon(&control_socket, addrs).await?;
+            }
+        }
+
+        Command::Export { file } => {
+            let store = Store::open_readonly(var_file).await?;
+            let buf = store.export()?;
+            if let Some(file) = file {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(0o600)
+                    .open(file)?;
+                file.write_all(buf.as_bytes())?;
+            } else {
+                io::stdout().write_all(buf.as_bytes())?;
+            }
+        }
+
+        Command::Import { file } => {
+            let buf = if let Some(file) = file {
+                fs::read_to_string(file)?
+            } else {
+                let mut buf = String::new();
+                io::stdin().read_to_string(&mut buf)?;
+                buf
+            };
+            // the daemon never reloads the keys.
+            let mut store = match Store::open(&var_file).await {
+                Err(KeydbError::Locked(..)) => anyhow::bail!(
+                    "{} is held by the running daemon. stop it before importing.",
+                    var_file.display()
+                ),
+                result => result?,
+            };
+            store.import(&buf).await?;
+            log::info!("imported.");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,441 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context as _;
+use serde::Deserialize;
+
+use crate::hid::KeyboardUsageId;
+use crate::Chord;
+
+/// Settings loaded from the configuration file.
+///
+/// All of the fields are optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub device: Device,
+    pub advertising: Advertising,
+    pub pairing: Pairing,
+    pub hid: Hid,
+    pub battery: Battery,
+    pub input: Input,
+    pub remap: Remap,
+}
+
+impl Config {
+    pub fn load<P>(path: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let buf = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&buf).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    fn parse(buf: &[u8]) -> anyhow::Result<Self> {
+        let config = toml::from_slice::<Self>(buf)?;
+        anyhow::ensure!(
+            config.advertising.min_interval <= config.advertising.max_interval,
+            "advertising.min_interval must not exceed advertising.max_interval"
+        );
+        if !config.input.paths.is_empty() {
+            let input = &config.input;
+            let rules = input
+                .allow
+                .iter()
+                .chain(&input.deny)
+                .chain(&input.no_grab)
+                .chain(config.remap.device.iter().map(|d| &d.rule));
+            for rule in rules {
+                anyhow::ensure!(
+                    !rule.needs_udev(),
+                    "syspath and property rules need udev. they never match with input.paths"
+                );
+            }
+        }
+        // also the length of the pairing mode.
+        anyhow::ensure!(
+            config.advertising.timeout > 0,
+            "advertising.timeout must be positive"
+        );
+        Ok(config)
+    }
+}
+
+/// Device identity exposed to the hosts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Device {
+    pub name: String,
+    pub short_name: String,
+    /// GAP Appearance. (default: 0x03C0 HID generic)
+    pub appearance: u16,
+    pub manufacturer: String,
+    pub model: String,
+    pub serial: String,
+    pub pnp_id: PnpId,
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        Self {
+            name: "btknmle".into(),
+            short_name: "btknmle".into(),
+            appearance: 0x03C0,
+            manufacturer: "MYMANUFACTURE".into(),
+            model: "1234".into(),
+            serial: "9999".into(),
+            pnp_id: Default::default(),
+        }
+    }
+}
+
+/// Device Information Service PnP ID.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PnpId {
+    /// 0x01: Bluetooth SIG assigned, 0x02: USB Implementer's Forum assigned.
+    pub vendor_id_source: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub version: u16,
+}
+
+impl Default for PnpId {
+    fn default() -> Self {
+        Self {
+            vendor_id_source: 0x02,
+            vendor_id: 0x0000,
+            product_id: 0x0000,
+            version: 0x0000,
+        }
+    }
+}
+
+impl PnpId {
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![self.vendor_id_source];
+        buf.extend(self.vendor_id.to_le_bytes());
+        buf.extend(self.product_id.to_le_bytes());
+        buf.extend(self.version.to_le_bytes());
+        buf
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdvertisingPolicy {
+    /// Discoverable for `timeout` seconds. Restarted by a key press (or `pairing_chord`).
+    Timed,
+    /// Always discoverable.
+    Always,
+    /// Always connectable but not discoverable. Only the bonded hosts can connect.
+    Whitelist,
+    /// Same as `whitelist` but only the last connected host can connect.
+    ///
+    /// The mgmt API has no directed advertising. So it is emulated by rejecting the others.
+    Directed,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Advertising {
+    /// Advertising intervals in units of 0.625ms.
+    pub min_interval: u16,
+    pub max_interval: u16,
+    pub policy: AdvertisingPolicy,
+    /// Seconds to be discoverable. (`timed` or pairing mode)
+    pub timeout: u16,
+    /// Enters pairing mode (discoverable and bondable for `timeout` seconds).
+    /// With `timed` policy, only it restarts advertising instead of any key.
+    pub pairing_chord: Option<Chord>,
+}
+
+impl Default for Advertising {
+    fn default() -> Self {
+        Self {
+            min_interval: 224, // 140ms
+            max_interval: 338, // 211.25ms
+            policy: AdvertisingPolicy::Timed,
+            timeout: 60,
+            pairing_chord: None,
+        }
+    }
+}
+
+/// Optional HID reports.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hid {
+    /// Absolute pointer for touchscreens and tablets.
+    pub digitizer: bool,
+    /// High resolution wheel and AC Pan for the hosts supporting it.
+    pub resolution_multiplier: bool,
+    /// N-key rollover keyboard report instead of 6KRO.
+    /// Switched at runtime by the control socket.
+    pub nkro: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatteryProvider {
+    /// Always `level`.
+    Fixed,
+    /// System battery in `/sys/class/power_supply` or `path`.
+    Sysfs,
+    /// The lowest battery of the peripherals. (e.g. wireless keyboards)
+    Device,
+}
+
+/// Pairing with the new hosts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Pairing {
+    pub io_capability: IoCapability,
+    /// Seconds to wait for the passkey input or the confirmation.
+    pub passkey_timeout: u64,
+    /// Seconds for the new hosts to bond after connected. Disconnected after it.
+    pub bond_timeout: u64,
+    /// Failed pairings from the same address before the lockout. (0 disables it)
+    /// Only works for the public and static addresses.
+    pub max_failures: u32,
+    /// Failed pairings from any address before rejecting the new hosts. (0 disables it)
+    pub max_total_failures: u32,
+    /// Seconds to reject the locked out address or the new hosts.
+    pub lockout: u64,
+}
+
+impl Default for Pairing {
+    fn default() -> Self {
+        Self {
+            io_capability: IoCapability::KeyboardOnly,
+            passkey_timeout: 60,
+            bond_timeout: 90,
+            max_failures: 3,
+            max_total_failures: 10,
+            lockout: 300,
+        }
+    }
+}
+
+/// Input and output capability used to select the pairing method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoCapability {
+    /// The passkey is shown for the host to type.
+    DisplayOnly,
+    /// Numeric comparison confirmed by Y or N.
+    DisplayYesNo,
+    /// The passkey shown on the host is typed on the keyboard.
+    KeyboardOnly,
+    /// Just Works. (unauthenticated)
+    NoInputNoOutput,
+    /// Numeric comparison with the LE Secure Connections hosts. Otherwise passkey input.
+    KeyboardDisplay,
+}
+
+/// Battery level reported in the Battery Service.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Battery {
+    pub provider: BatteryProvider,
+    /// For `fixed` provider.
+    pub level: u8,
+    /// Capacity file for `sysfs` provider. (e.g. /sys/class/power_supply/BAT0/capacity)
+    pub path: Option<PathBuf>,
+    /// Polling interval in seconds.
+    pub interval: u64,
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Self {
+            provider: BatteryProvider::Fixed,
+            level: 100,
+            path: None,
+            interval: 60,
+        }
+    }
+}
+
+/// When to grab the input devices exclusively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GrabPolicy {
+    Never,
+    /// While any host is connected and not suspended. (or passkey input)
+    WhileConnected,
+    Always,
+}
+
+impl FromStr for GrabPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" | "no" | "false" => Ok(Self::Never),
+            // `--grab` / `BTKNMLE_GRAB=yes` used to be a flag.
+            "while-connected" | "yes" | "true" => Ok(Self::WhileConnected),
+            "always" => Ok(Self::Always),
+            _ => anyhow::bail!("unknown grab policy: {}", s),
+        }
+    }
+}
+
+/// Input devices to forward.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Input {
+    /// udev seat.
+    pub seat: String,
+    /// Use these device files instead of udev. Directories are watched for event devices.
+    pub paths: Vec<PathBuf>,
+    /// Forward only the devices matching any of them. (all devices if empty)
+    pub allow: Vec<DeviceRule>,
+    /// Ignore the devices matching any of them.
+    pub deny: Vec<DeviceRule>,
+    pub grab: GrabPolicy,
+    /// Never grab the devices matching any of them.
+    pub no_grab: Vec<DeviceRule>,
+    /// Holding it releases the grab and detaches from the hosts. Holding again reattaches.
+    pub escape: Chord,
+    /// Seconds to hold `escape`.
+    pub escape_hold: u64,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self {
+            seat: "seat0".into(),
+            paths: vec![],
+            allow: vec![],
+            deny: vec![],
+            grab: GrabPolicy::Never,
+            no_grab: vec![],
+            escape: "ctrl+alt+esc".parse().unwrap(),
+            escape_hold: 3,
+        }
+    }
+}
+
+/// Matches if all of the specified conditions match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceRule {
+    /// Device name.
+    pub name: Option<String>,
+    pub vendor_id: Option<u32>,
+    pub product_id: Option<u32>,
+    /// Prefix of the sysfs path. (e.g. /sys/devices/pci0000:00/0000:00:14.0/usb1/1-2)
+    pub syspath: Option<PathBuf>,
+    /// udev properties.
+    pub property: BTreeMap<String, String>,
+}
+
+impl DeviceRule {
+    /// Not available for the devices opened by `Input::paths`.
+    pub(crate) fn needs_udev(&self) -> bool {
+        self.syspath.is_some() || !self.property.is_empty()
+    }
+}
+
+/// Key remapping. Key names are same as the chords. (e.g. `capslock`, `left_ctrl`)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Remap {
+    /// For all devices.
+    pub keys: HashMap<KeyboardUsageId, KeyboardUsageId>,
+    /// Override `keys` for the matching devices.
+    pub device: Vec<DeviceRemap>,
+    /// Override the others while active.
+    pub layer: Vec<Layer>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceRemap {
+    #[serde(rename = "match")]
+    pub rule: DeviceRule,
+    pub keys: HashMap<KeyboardUsageId, KeyboardUsageId>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayerMode {
+    /// Active until the key is pressed again.
+    #[default]
+    Toggle,
+    /// Active while the key is held.
+    Hold,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layer {
+    /// Activates the layer. It is never sent to the hosts.
+    pub key: KeyboardUsageId,
+    #[serde(default)]
+    pub mode: LayerMode,
+    pub keys: HashMap<KeyboardUsageId, KeyboardUsageId>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::parse(b"").unwrap();
+        assert_eq!(config.device.name, "btknmle");
+        assert_eq!(config.device.appearance, 0x03C0);
+        assert_eq!(config.device.pnp_id.vendor_id_source, 0x02);
+        assert_eq!(config.advertising.min_interval, 224);
+        assert_eq!(config.advertising.policy, AdvertisingPolicy::Timed);
+        assert_eq!(config.pairing.io_capability, IoCapability::KeyboardOnly);
+        assert_eq!(config.input.grab, GrabPolicy::Never);
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            br#"
+            [device]
+            name = "desk1"
+
+            [device.pnp_id]
+            vendor_id = 0x046d
+
+            [advertising]
+            policy = "whitelist"
+
+            [pairing]
+            io_capability = "display-yes-no"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.device.name, "desk1");
+        // the others are default.
+        assert_eq!(config.device.short_name, "btknmle");
+        assert_eq!(config.device.pnp_id.vendor_id, 0x046d);
+        assert_eq!(config.device.pnp_id.vendor_id_source, 0x02);
+        assert_eq!(config.advertising.policy, AdvertisingPolicy::Whitelist);
+        assert_eq!(config.advertising.max_interval, 338);
+        assert_eq!(config.pairing.io_capability, IoCapability::DisplayYesNo);
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        assert!(Config::parse(b"nmae = \"desk1\"").is_err());
+        assert!(Config::parse(b"[device]\nnmae = \"desk1\"").is_err());
+        assert!(Config::parse(b"[device.pnp_id]\nvendor = 1").is_err());
+        assert!(Config::parse(b"[advertising]\npolicy = \"never\"").is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Config::parse(b"[advertising]\nmin_interval = 400").is_err());
+        assert!(Config::parse(b"[advertising]\ntimeout = 0").is_err());
+    }
+}
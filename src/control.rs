@@ -0,0 +1,319 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt as _, PermissionsExt as _};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use btmgmt::client::Client as MgmtClient;
+use btmgmt::packet::{command as cmd, ControllerIndex};
+use futures_channel::mpsc;
+use futures_util::stream::FuturesUnordered;
+use futures_util::{select, FutureExt as _, StreamExt as _};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt as _, AsyncWrite, AsyncWriteExt as _, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{broadcast, watch};
+
+use crate::input::{Chord, InputSource, Layout};
+use crate::monitor::Monitor;
+pub use crate::monitor::{Event, Host, Passkey, Status};
+
+fn default_delay() -> u64 {
+    10
+}
+
+/// Request to the control socket. One JSON value per line.
+///
+/// Hosts are numbered from 1 as the host switch chords.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    /// Stream the events after `ok`.
+    Subscribe,
+    /// Same as the pairing chord.
+    StartAdvertising,
+    /// Restarted as the advertising policy.
+    StopAdvertising,
+    Disconnect {
+        host: usize,
+    },
+    SwitchHost {
+        host: usize,
+    },
+    /// Unpair the bonded host. (identity address)
+    ForgetBond {
+        address: String,
+    },
+    /// Type the text on the active host.
+    Type {
+        text: String,
+        #[serde(default)]
+        layout: Layout,
+        /// Milliseconds after each key press and release.
+        #[serde(default = "default_delay")]
+        delay: u64,
+    },
+    /// Press and release the chords on the active host. (e.g. `ctrl+alt+delete`)
+    Keys {
+        chords: Vec<String>,
+        #[serde(default = "default_delay")]
+        delay: u64,
+    },
+    /// Switch the keyboard report of the hosts between NKRO and 6KRO. (needs `hid.nkro`)
+    SetNkro {
+        enabled: bool,
+    },
+}
+
+/// Returned for each request line. `event` is only sent after `subscribe`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Error(String),
+    Status(Status),
+    Event(Event),
+}
+
+struct Context<'a> {
+    device_id: ControllerIndex,
+    gap: &'a MgmtClient,
+    input: InputSource,
+    pairing: mpsc::UnboundedSender<()>,
+    nkro: Option<&'a watch::Sender<bool>>,
+    monitor: Monitor,
+}
+
+fn slot(host: usize) -> anyhow::Result<usize> {
+    host.checked_sub(1)
+        .ok_or_else(|| anyhow::anyhow!("hosts are numbered from 1"))
+}
+
+async fn handle(ctx: &Context<'_>, request: Request) -> anyhow::Result<Response> {
+    match request {
+        Request::Status => return Ok(Response::Status(ctx.monitor.status())),
+        // handled by the connection.
+        Request::Subscribe => {}
+        Request::StartAdvertising => ctx.pairing.unbounded_send(())?,
+        Request::StopAdvertising => {
+            crate::gap::stop_advertising(ctx.gap, ctx.device_id.clone()).await?;
+        }
+        Request::Disconnect { host } => {
+            let addr = ctx
+                .monitor
+                .host_address(slot(host)?)
+                .ok_or_else(|| anyhow::anyhow!("host {} not connected", host))?;
+            ctx.gap
+                .call(ctx.device_id.clone(), cmd::Disconnect::new(addr))
+                .await?;
+        }
+        Request::SwitchHost { host } => ctx.input.switch_host(slot(host)?).await?,
+        Request::ForgetBond { address } => {
+            let addr = ctx
+                .monitor
+                .bonds()
+                .into_iter()
+                .find(|addr| addr.to_string().eq_ignore_ascii_case(&address))
+                .ok_or_else(|| anyhow::anyhow!("no bond found for {}", address))?;
+            // events are not delivered to the socket issuing the command.
+            // use another socket to receive Device Unpaired on the daemon socket.
+            MgmtClient::open()?
+                .call(ctx.device_id.clone(), cmd::UnpairDevice::new(addr, true))
+                .await?;
+        }
+        Request::Type {
+            text,
+            layout,
+            delay,
+        } => {
+            ctx.input
+                .type_text(&text, layout, Duration::from_millis(delay))
+                .await?
+        }
+        Request::Keys { chords, delay } => {
+            let chords = chords
+                .iter()
+                .map(|c| c.parse::<Chord>())
+                .collect::<Result<Vec<_>, _>>()?;
+            ctx.input
+                .press_chords(&chords, Duration::from_millis(delay))
+                .await?
+        }
+        Request::SetNkro { enabled } => ctx
+            .nkro
+            .ok_or_else(|| anyhow::anyhow!("NKRO report is not enabled (hid.nkro)"))?
+            .send(enabled)?,
+    }
+    Ok(Response::Ok)
+}
+
+async fn write_line<W, T>(write: &mut W, value: &T) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut buf = serde_json::to_vec(value)?;
+    buf.push(b'\n');
+    write.write_all(&buf).await?;
+    Ok(())
+}
+
+/// Pending forever if not subscribed.
+async fn next_event(events: &mut Option<broadcast::Receiver<Event>>) -> Event {
+    loop {
+        let result = match events {
+            Some(events) => events.recv().await,
+            None => futures_util::future::pending().await,
+        };
+        match result {
+            Ok(event) => return event,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                log::warn!("control subscriber lagged {} events.", n)
+            }
+            Err(broadcast::error::RecvError::Closed) => *events = None,
+        }
+    }
+}
+
+async fn serve_connection(ctx: &Context<'_>, stream: UnixStream) -> anyhow::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let mut events = None;
+    loop {
+        select! {
+            line = lines.next_line().fuse() => {
+                let line = if let Some(line) = line? {
+                    line
+                } else {
+                    return Ok(());
+                };
+                let result = match serde_json::from_str::<Request>(&line) {
+                    Ok(Request::Subscribe) => {
+                        events = Some(ctx.monitor.subscribe());
+                        Ok(Response::Ok)
+                    }
+                    Ok(request) => handle(ctx, request).await,
+                    Err(err) => Err(err.into()),
+                };
+                let response = result.unwrap_or_else(|err| Response::Error(err.to_string()));
+                write_line(&mut write, &response).await?;
+            }
+
+            event = next_event(&mut events).fuse() => {
+                write_line(&mut write, &Response::Event(event)).await?;
+            }
+        }
+    }
+}
+
+/// Accept the connections. The socket is only accessible by the owner.
+/// Bind the socket with mode 0600 from the start.
+/// It is bound in a private directory, then moved to `path`.
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    let dir = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let tmp = dir.join("sock");
+    let result = UnixListener::bind(&tmp).and_then(|listener| {
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&tmp, path)?;
+        Ok(listener)
+    });
+    fs::remove_dir_all(&dir).ok();
+    result
+}
+
+pub(crate) async fn serve(
+    path: PathBuf,
+    device_id: ControllerIndex,
+    gap: &MgmtClient,
+    input: InputSource,
+    pairing: mpsc::UnboundedSender<()>,
+    nkro: Option<&watch::Sender<bool>>,
+    monitor: Monitor,
+) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if UnixStream::connect(&path).await.is_ok() {
+        anyhow::bail!("another daemon is listening on {}", path.display());
+    }
+    // replaces the socket left by the previous run.
+    let listener = bind(&path)?;
+
+    let ctx = Context {
+        device_id,
+        gap,
+        input,
+        pairing,
+        nkro,
+        monitor,
+    };
+    let mut connections = FuturesUnordered::new();
+    loop {
+        select! {
+            accepted = listener.accept().fuse() => {
+                let (stream, _) = accepted?;
+                connections.push(serve_connection(&ctx, stream));
+            }
+
+            result = connections.select_next_some() => {
+                if let Err(err) = result {
+                    log::warn!("control connection: {}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Send a request to the running daemon.
+pub async fn request<P>(path: P, request: &Request) -> anyhow::Result<Response>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let stream = UnixStream::connect(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to connect {}: {}", path.display(), e))?;
+    let (read, mut write) = stream.into_split();
+    write_line(&mut write, request).await?;
+
+    let line = BufReader::new(read)
+        .lines()
+        .next_line()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("connection closed"))?;
+    match serde_json::from_str(&line)? {
+        Response::Error(err) => anyhow::bail!(err),
+        response => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind() {
+        let dir = std::env::temp_dir().join(format!("btknmle-control-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("control.sock");
+        // stale socket of the previous run.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let _listener = bind(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(UnixStream::connect(&path).await.is_ok());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_nkro() {
+        let request = serde_json::from_str(r#"{"set_nkro": {"enabled": false}}"#).unwrap();
+        assert!(matches!(request, Request::SetNkro { enabled: false }));
+    }
+}
@@ -0,0 +1,127 @@
+//! Select the controller and follow its hotplug.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bdaddr::BdAddr;
+use btmgmt::client::Client as MgmtClient;
+use btmgmt::packet::command as cmd;
+use btmgmt::packet::event::Event as MgmtEvent;
+use btmgmt::packet::ControllerIndex;
+use futures_util::StreamExt as _;
+
+/// Controller selected by the index (`0` or `hci0`) or the public address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Controller {
+    Index(u16),
+    Address(BdAddr),
+}
+
+impl FromStr for Controller {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let index = s.strip_prefix("hci").unwrap_or(s);
+        if !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(Self::Index(index.parse()?));
+        }
+        match s.parse() {
+            Ok(addr) => Ok(Self::Address(addr)),
+            Err(..) => anyhow::bail!("invalid controller: {} (index or address expected)", s),
+        }
+    }
+}
+
+impl fmt::Display for Controller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "hci{}", index),
+            Self::Address(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+impl Controller {
+    /// Returns the index if the controller is present.
+    pub(crate) async fn find(&self, client: &MgmtClient) -> anyhow::Result<Option<u16>> {
+        let indices = client
+            .call(ControllerIndex::NonController, cmd::ReadControllerIndexList)
+            .await?;
+        for index in indices {
+            let index = u16::from(index);
+            match self {
+                Self::Index(expected) if *expected == index => return Ok(Some(index)),
+                Self::Index(..) => {}
+                Self::Address(addr) => {
+                    match client.call(index, cmd::ReadControllerInformation).await {
+                        Ok(info) if info.address() == addr => return Ok(Some(index)),
+                        Ok(..) => {}
+                        // e.g. removed while reading.
+                        Err(err) => log::debug!("failed to read hci{}: {}", index, err),
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Wait until the controller is added.
+    pub(crate) async fn wait(&self, client: &MgmtClient) -> anyhow::Result<u16> {
+        // subscribe before looking up not to miss Index Added.
+        let mut events = client.events().await;
+        let mut waiting = false;
+        loop {
+            if let Some(index) = self.find(client).await? {
+                return Ok(index);
+            }
+            if !waiting {
+                log::info!("waiting for controller {}.", self);
+                waiting = true;
+            }
+            loop {
+                match events.next().await {
+                    Some((_, MgmtEvent::IndexAdded(..))) => break,
+                    Some(..) => {}
+                    None => anyhow::bail!("mgmt event stream closed."),
+                }
+            }
+        }
+    }
+}
+
+/// Returns when the controller is removed. (e.g. the USB dongle is unplugged or reset)
+pub(crate) async fn removed(client: &MgmtClient, index: u16) -> anyhow::Result<()> {
+    let mut events = client.events().await;
+    let index = ControllerIndex::from(index);
+    while let Some((idx, event)) = events.next().await {
+        if idx == index && matches!(event, MgmtEvent::IndexRemoved(..)) {
+            return Ok(());
+        }
+    }
+    anyhow::bail!("mgmt event stream closed.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("0".parse::<Controller>().unwrap(), Controller::Index(0));
+        assert_eq!("hci1".parse::<Controller>().unwrap(), Controller::Index(1));
+        assert_eq!(
+            "00:11:22:33:44:55".parse::<Controller>().unwrap(),
+            Controller::Address([0x55, 0x44, 0x33, 0x22, 0x11, 0x00].into())
+        );
+        assert!("hci".parse::<Controller>().is_err());
+        assert!("hci65536".parse::<Controller>().is_err());
+        assert!("garbage".parse::<Controller>().is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Controller::Index(0).to_string(), "hci0");
+        let addr = "00:11:22:33:44:55";
+        assert_eq!(addr.parse::<Controller>().unwrap().to_string(), addr);
+    }
+}
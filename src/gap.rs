@@ -6,10 +6,25 @@ use btmgmt::packet::{
     Settings, SystemConfigurationParameter,
 };
 
+use crate::config::{self, Config};
+
+impl From<config::IoCapability> for IoCapability {
+    fn from(v: config::IoCapability) -> Self {
+        match v {
+            config::IoCapability::DisplayOnly => Self::DisplayOnly,
+            config::IoCapability::DisplayYesNo => Self::DisplayYesNo,
+            config::IoCapability::KeyboardOnly => Self::KeyboardOnly,
+            config::IoCapability::NoInputNoOutput => Self::NoInputNoOutput,
+            config::IoCapability::KeyboardDisplay => Self::KeyboardDisplay,
+        }
+    }
+}
+
 pub(crate) async fn setup(
     devid: u16,
     store: &Store,
     io_capability: IoCapability,
+    config: &Config,
 ) -> anyhow::Result<Client> {
     let client = Client::open()?;
 
@@ -43,11 +58,16 @@ pub(crate) async fn setup(
         )
         .await?;
 
-    client.call(devid, cmd::SetApperance::new(0x03c0)).await?;
+    client
+        .call(devid, cmd::SetApperance::new(config.device.appearance))
+        .await?;
     client
         .call(
             devid,
-            cmd::SetLocalName::new("btknmle".parse()?, "btknmle".parse()?),
+            cmd::SetLocalName::new(
+                config.device.name.parse()?,
+                config.device.short_name.parse()?,
+            ),
         )
         .await?;
 
@@ -79,8 +99,12 @@ pub(crate) async fn setup(
         .call(
             devid,
             vec![
-                SystemConfigurationParameter::LEAdvertisementMinInterval(224), // 140ms
-                SystemConfigurationParameter::LEAdvertisementMaxInterval(338), // 211.25ms
+                SystemConfigurationParameter::LEAdvertisementMinInterval(
+                    config.advertising.min_interval,
+                ),
+                SystemConfigurationParameter::LEAdvertisementMaxInterval(
+                    config.advertising.max_interval,
+                ),
             ]
             .into_iter()
             .collect::<cmd::SetDefaultSystemConfiguration>(),
@@ -92,21 +116,28 @@ pub(crate) async fn setup(
     Ok(client)
 }
 
+/// Connectable advertising. `timeout` 0 means no timeout.
 pub(crate) async fn start_advertising(
     client: &Client,
     devid: ControllerIndex,
+    discoverable: bool,
     timeout: u16,
 ) -> anyhow::Result<()> {
+    let mut flags = AdvertisingFlag::SwitchIntoConnectableMode
+        | AdvertisingFlag::AddFlagsFieldToAdvData
+        | AdvertisingFlag::AddAppearanceFieldToScanResp
+        | AdvertisingFlag::AddLocalNameInScanResp;
+    if discoverable && timeout > 0 {
+        flags |= AdvertisingFlag::AdvertiseAsLimitedDiscoverable;
+    } else if discoverable {
+        flags |= AdvertisingFlag::AdvertiseAsDiscoverable;
+    }
     client
         .call(
             devid,
             cmd::AddAdvertising::new(
                 1.into(),
-                AdvertisingFlag::SwitchIntoConnectableMode
-                    | AdvertisingFlag::AdvertiseAsLimitedDiscoverable
-                    | AdvertisingFlag::AddFlagsFieldToAdvData
-                    | AdvertisingFlag::AddAppearanceFieldToScanResp
-                    | AdvertisingFlag::AddLocalNameInScanResp,
+                flags,
                 0,
                 timeout,
                 AdvDataScanResp::new(vec![], vec![0x03, 0x03, 0x12, 0x18]),
@@ -126,6 +157,16 @@ pub(crate) async fn stop_advertising(
     Ok(())
 }
 
+/// New hosts can not pair while not bondable.
+pub(crate) async fn set_bondable(
+    client: &Client,
+    devid: ControllerIndex,
+    bondable: bool,
+) -> anyhow::Result<()> {
+    client.call(devid, cmd::SetBondable::new(bondable)).await?;
+    Ok(())
+}
+
 pub(crate) async fn is_advertising_enabled(
     client: &Client,
     devid: ControllerIndex,
@@ -1,5 +1,7 @@
 #![warn(clippy::all)]
 
+use serde::{Deserialize, Deserializer};
+
 #[derive(thiserror::Error, Debug)]
 #[error("no mapping found {0:?}")]
 pub struct NoMappingFound(btknmle_input::KeyCodes);
@@ -10,14 +12,18 @@ impl From<btknmle_input::KeyCodes> for NoMappingFound {
     }
 }
 
-macro_rules! kbd_usage_id {
+#[derive(thiserror::Error, Debug)]
+#[error("unknown usage {0}")]
+pub struct UnknownUsage(String);
+
+macro_rules! usage_id {
     (
         $(#[$attrs:meta])*
-        $vis:vis enum $name:ident: $fromty:ty {
+        $vis:vis enum $name:ident: $fromty:ty => $valty:ty {
             type Error = $errty:ty;
             $(
                 $(#[$fattr:meta])*
-                $fname:ident : $fval:literal $( => $from:ident)?,
+                $fname:ident : $fval:literal $( => $($from:ident)|+)?,
             )*
         }
     ) => {
@@ -35,14 +41,27 @@ macro_rules! kbd_usage_id {
             fn try_from(v: $fromty) -> Result<Self, Self::Error> {
                 match v {
                     $(
-                        $(<$fromty>::$from => Ok(Self::$fname),)?
+                        $($(<$fromty>::$from)|+ => Ok(Self::$fname),)?
                     )*
                     x => Err(Self::Error::from(x)),
                 }
             }
         }
 
-        impl From<$name> for u8 {
+        impl std::str::FromStr for $name {
+            type Err = UnknownUsage;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $(
+                        stringify!($fname) => Ok(Self::$fname),
+                    )*
+                    x => Err(UnknownUsage(x.to_string())),
+                }
+            }
+        }
+
+        impl From<$name> for $valty {
             fn from(v: $name) -> Self {
                 match v {
                     $(
@@ -54,10 +73,10 @@ macro_rules! kbd_usage_id {
     }
 }
 
-kbd_usage_id! {
+usage_id! {
     #[allow(non_camel_case_types)]
     #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-    pub enum KeyboardUsageId: btknmle_input::KeyCodes {
+    pub enum KeyboardUsageId: btknmle_input::KeyCodes => u8 {
         type Error = NoMappingFound;
 
         KEY_ERROR_ROLLOVER: 0x01,
@@ -186,9 +205,10 @@ kbd_usage_id! {
         KEY_COPY: 0x7C => KEY_COPY,
         KEY_PASTE: 0x7D => KEY_PASTE,
         KEY_FIND: 0x7E => KEY_FIND,
-        KEY_MUTE: 0x7F => KEY_MUTE,
-        KEY_VOLUMEUP: 0x80 => KEY_VOLUMEUP,
-        KEY_VOLUMEDOWN: 0x81 => KEY_VOLUMEDOWN,
+        // KEY_MUTE, KEY_VOLUMEUP and KEY_VOLUMEDOWN are sent as ConsumerUsageId.
+        KEY_MUTE: 0x7F,
+        KEY_VOLUMEUP: 0x80,
+        KEY_VOLUMEDOWN: 0x81,
         KEY_LOCKING_CAPSLOCK: 0x82,
         KEY_LOCKING_NUMLOCK: 0x83,
         KEY_LOCKING_SCROLLOCK: 0x84,
@@ -278,25 +298,72 @@ kbd_usage_id! {
         KEY_RIGHT_SHIFT: 0xE5 => KEY_RIGHTSHIFT,
         KEY_RIGHT_ALT: 0xE6 => KEY_RIGHTALT,
         KEY_RIGHT_GUI: 0xE7 => KEY_RIGHTMETA,
-        KEY_PLAYPAUSE: 0xE8 => KEY_PLAYPAUSE,
-        KEY_STOPCD: 0xE9 => KEY_STOPCD,
-        KEY_PREVIOUSSONG: 0xEA => KEY_PREVIOUSSONG,
-        KEY_NEXTSONG: 0xEB => KEY_NEXTSONG,
-        KEY_EJECTCD: 0xEC => KEY_EJECTCD,
-        // KEY_VOLUMEUP: 0xED => KEY_VOLUMEUP,
-        // KEY_VOLUMEDOWN: 0xEE => KEY_VOLUMEDOWN,
-        // KEY_MUTE: 0xEF => KEY_MUTE,
-        KEY_WWW: 0xF0 => KEY_WWW,
-        KEY_BACK: 0xF1 => KEY_BACK,
-        KEY_FORWARD: 0xF2 => KEY_FORWARD,
-        // KEY_STOP: 0xF3 => KEY_STOP,
-        // KEY_FIND: 0xF4 => KEY_FIND,
-        KEY_SCROLLUP: 0xF5 => KEY_SCROLLUP,
-        KEY_SCROLLDOWN: 0xF6 => KEY_SCROLLDOWN,
-        KEY_EDIT: 0xF7 => KEY_EDIT,
-        KEY_SLEEP: 0xF8 => KEY_SLEEP,
-        KEY_COFFEE: 0xF9 => KEY_COFFEE,
-        KEY_REFRESH: 0xFA => KEY_REFRESH,
-        KEY_CALC: 0xFB => KEY_CALC,
+    }
+}
+
+/// Key name like `f1`, `capslock` or `KEY_LEFT_CTRL`. (case insensitive)
+///
+/// `delete` is the forward delete and `backspace` is the backspace
+/// as labeled on the keys. (the usage names are `KEY_DELETE_FORWARD` and `KEY_DELETE`)
+pub(crate) fn parse_key(name: &str) -> Result<KeyboardUsageId, UnknownUsage> {
+    let name = name.trim().to_ascii_uppercase();
+    match name.as_str() {
+        "DELETE" | "DEL" => Ok(KeyboardUsageId::KEY_DELETE_FORWARD),
+        "BACKSPACE" => Ok(KeyboardUsageId::KEY_DELETE),
+        _ if name.starts_with("KEY_") => name.parse(),
+        _ => format!("KEY_{}", name).parse(),
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyboardUsageId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        parse_key(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+usage_id! {
+    #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub enum ConsumerUsageId: btknmle_input::KeyCodes => u16 {
+        type Error = NoMappingFound;
+
+        POWER: 0x0030,
+        SLEEP: 0x0032 => KEY_SLEEP,
+        MENU: 0x0040 => KEY_MENU,
+        BRIGHTNESS_INCREMENT: 0x006F => KEY_BRIGHTNESSUP,
+        BRIGHTNESS_DECREMENT: 0x0070 => KEY_BRIGHTNESSDOWN,
+        PLAY: 0x00B0 => KEY_PLAY | KEY_PLAYCD,
+        PAUSE: 0x00B1 => KEY_PAUSECD,
+        RECORD: 0x00B2 => KEY_RECORD,
+        FAST_FORWARD: 0x00B3 => KEY_FASTFORWARD,
+        REWIND: 0x00B4 => KEY_REWIND,
+        SCAN_NEXT_TRACK: 0x00B5 => KEY_NEXTSONG,
+        SCAN_PREVIOUS_TRACK: 0x00B6 => KEY_PREVIOUSSONG,
+        STOP: 0x00B7 => KEY_STOPCD,
+        EJECT: 0x00B8 => KEY_EJECTCD,
+        PLAY_PAUSE: 0x00CD => KEY_PLAYPAUSE,
+        MUTE: 0x00E2 => KEY_MUTE,
+        VOLUME_INCREMENT: 0x00E9 => KEY_VOLUMEUP,
+        VOLUME_DECREMENT: 0x00EA => KEY_VOLUMEDOWN,
+        AL_CONSUMER_CONTROL_CONFIGURATION: 0x0183 => KEY_CONFIG | KEY_MEDIA,
+        AL_EMAIL_READER: 0x018A => KEY_MAIL,
+        AL_CALCULATOR: 0x0192 => KEY_CALC,
+        AL_LOCAL_MACHINE_BROWSER: 0x0194 => KEY_FILE | KEY_COMPUTER,
+        AL_INTERNET_BROWSER: 0x0196 => KEY_WWW,
+        AL_TERMINAL_LOCK: 0x019E => KEY_COFFEE,
+        AC_SEARCH: 0x0221 => KEY_SEARCH,
+        AC_HOME: 0x0223 => KEY_HOMEPAGE,
+        AC_BACK: 0x0224 => KEY_BACK,
+        AC_FORWARD: 0x0225 => KEY_FORWARD,
+        AC_STOP: 0x0226,
+        AC_REFRESH: 0x0227 => KEY_REFRESH,
+        AC_BOOKMARKS: 0x022A => KEY_BOOKMARKS,
+        AC_SCROLL_UP: 0x0233 => KEY_SCROLLUP,
+        AC_SCROLL_DOWN: 0x0234 => KEY_SCROLLDOWN,
+        AC_EDIT: 0x023D => KEY_EDIT,
     }
 }
@@ -2,12 +2,15 @@ use gatt::characteristics as ch;
 use gatt::services as srv;
 use gatt::{CharacteristicProperties, Registration};
 
-pub(crate) fn add(registration: &mut Registration<super::Token>) {
+/// `level` is returned for READ while connected, as the database has no read callback.
+/// Later changes are only notified.
+pub(crate) fn add(registration: &mut Registration<super::Token>, level: u8) {
     registration.add_primary_service(srv::BATTERY);
 
-    registration.add_characteristic(
+    registration.add_characteristic_with_token(
+        super::Token::Battery,
         ch::BATTERY_LEVEL,
-        vec![100],
-        CharacteristicProperties::INDICATE,
+        vec![level],
+        CharacteristicProperties::READ | CharacteristicProperties::NOTIFY,
     );
 }
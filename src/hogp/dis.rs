@@ -2,21 +2,28 @@ use gatt::characteristics as ch;
 use gatt::services as srv;
 use gatt::{CharacteristicProperties, Registration};
 
-pub(crate) fn add(registration: &mut Registration<super::Token>) {
+use crate::config::Device;
+
+pub(crate) fn add(registration: &mut Registration<super::Token>, device: &Device) {
     registration.add_primary_service(srv::DEVICE_INFORMATION);
     registration.add_characteristic(
         ch::MANUFACTURER_NAME_STRING,
-        "MYMANUFACTURE",
+        device.manufacturer.clone(),
         CharacteristicProperties::READ,
     );
     registration.add_characteristic(
         ch::MODEL_NUMBER_STRING,
-        "1234",
+        device.model.clone(),
         CharacteristicProperties::READ,
     );
     registration.add_characteristic(
         ch::SERIAL_NUMBER_STRING,
-        "9999",
+        device.serial.clone(),
+        CharacteristicProperties::READ,
+    );
+    registration.add_characteristic(
+        ch::PNP_ID,
+        device.pnp_id.to_bytes(),
         CharacteristicProperties::READ,
     );
 }
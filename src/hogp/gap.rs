@@ -2,15 +2,21 @@ use gatt::characteristics as ch;
 use gatt::services as srv;
 use gatt::{CharacteristicProperties, Registration};
 
-pub(crate) fn add(registration: &mut Registration<super::Token>) {
+use crate::config::Device;
+
+pub(crate) fn add(registration: &mut Registration<super::Token>, device: &Device) {
     registration.add_primary_service(srv::GENERIC_ACCESS);
 
     // Device Name
-    registration.add_characteristic(ch::DEVICE_NAME, "btknmle", CharacteristicProperties::READ);
-    // Appearance [HID generic]
+    registration.add_characteristic(
+        ch::DEVICE_NAME,
+        device.name.clone(),
+        CharacteristicProperties::READ,
+    );
+    // Appearance
     registration.add_characteristic(
         ch::APPEARANCE,
-        vec![0xC0, 0x03],
+        device.appearance.to_le_bytes(),
         CharacteristicProperties::READ,
     );
 }
@@ -2,81 +2,192 @@ use gatt::characteristics as ch;
 use gatt::services as srv;
 use gatt::{CharacteristicProperties, Registration, Uuid};
 
-pub(crate) fn add(registration: &mut Registration<super::Token>) {
+use crate::config::Hid;
+
+const NKRO_REPORT_MAP: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xa1, 0x01, // Collection (Application)
+    0x85, 0x05, // Report ID 0x05
+    0x05, 0x07, // Usage Page (Keyboard/Keypad)
+    0x18, // UsageMin (0)
+    0x29, 0xe7, // UsageMax (0xE7)
+    0x14, // LogicalMin (0)
+    0x25, 0x01, // LogicalMax (1)
+    0x75, 0x01, // Report Size (1)
+    0x96, 0xe8, 0x00, // Report Count (232)
+    0x81, 0x02, // Input (Data,Var,Abs)
+    0xc0, // End Collection
+];
+
+const SCROLL_REPORT_MAP: &[u8] = &[
+    0x15, 0x81, // LogicalMin (0x81)
+    0x25, 0x7f, // LogicalMax (0x7f)
+    0x75, 0x08, // Report Size (8)
+    0x95, 0x01, // Report Count (1)
+    0x09, 0x38, // Usage (Wheel)
+    0x81, 0x06, // Input (Rel Wrap)
+    0x05, 0x0c, // Usage Page (Consumer)
+    0x0a, 0x38, 0x02, // Usage (AC Pan)
+    0x81, 0x06, // Input (Rel Wrap)
+];
+
+/// Wheel and AC Pan with Resolution Multiplier feature. (1 byte feature report)
+const HIRES_SCROLL_REPORT_MAP: &[u8] = &[
+    0xa1, 0x02, // Collection (Logical)
+    0x09, 0x48, // Usage (Resolution Multiplier)
+    0x14, // LogicalMin (0)
+    0x25, 0x01, // LogicalMax (1)
+    0x35, 0x01, // PhysicalMin (1)
+    0x45, 0x08, // PhysicalMax (8)
+    0x75, 0x02, // Report Size (2)
+    0x95, 0x01, // Report Count (1)
+    0xb1, 0x02, // Feature (Data,Var,Abs)
+    0x34, // PhysicalMin (0)
+    0x44, // PhysicalMax (0)
+    0x15, 0x81, // LogicalMin (0x81)
+    0x25, 0x7f, // LogicalMax (0x7f)
+    0x75, 0x08, // Report Size (8)
+    0x09, 0x38, // Usage (Wheel)
+    0x81, 0x06, // Input (Rel Wrap)
+    0xc0, // End Collection
+    0xa1, 0x02, // Collection (Logical)
+    0x09, 0x48, // Usage (Resolution Multiplier)
+    0x14, // LogicalMin (0)
+    0x25, 0x01, // LogicalMax (1)
+    0x35, 0x01, // PhysicalMin (1)
+    0x45, 0x08, // PhysicalMax (8)
+    0x75, 0x02, // Report Size (2)
+    0xb1, 0x02, // Feature (Data,Var,Abs)
+    0x34, // PhysicalMin (0)
+    0x44, // PhysicalMax (0)
+    0x15, 0x81, // LogicalMin (0x81)
+    0x25, 0x7f, // LogicalMax (0x7f)
+    0x75, 0x08, // Report Size (8)
+    0x05, 0x0c, // Usage Page (Consumer)
+    0x0a, 0x38, 0x02, // Usage (AC Pan)
+    0x81, 0x06, // Input (Rel Wrap)
+    0xc0, // End Collection
+    0x75, 0x04, // Report Size (4)
+    0xb1, 0x03, // Feature (Const)
+];
+
+const DIGITIZER_REPORT_MAP: &[u8] = &[
+    0x05, 0x0d, // Usage Page (Digitizer)
+    0x09, 0x02, // Usage (Pen)
+    0xa1, 0x01, // Collection (Application)
+    0x85, 0x04, // Report ID 0x04
+    0x09, 0x20, // Usage (Stylus)
+    0xa1, 0x00, // Collection (Physical)
+    0x09, 0x42, // Usage (Tip Switch)
+    0x09, 0x32, // Usage (In Range)
+    0x14, // LogicalMin (0)
+    0x25, 0x01, // LogicalMax (1)
+    0x75, 0x01, // Report Size (1)
+    0x95, 0x02, // Report Count (2)
+    0x81, 0x02, // Input (Data,Var,Abs)
+    0x95, 0x06, // Report Count (6)
+    0x81, 0x03, // Input (Const,Var,Abs)
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x26, 0xff, 0x7f, // LogicalMax (0x7FFF)
+    0x75, 0x10, // Report Size (16)
+    0x95, 0x01, // Report Count (1)
+    0x09, 0x30, // Usage (X)
+    0x81, 0x02, // Input (Data,Var,Abs)
+    0x09, 0x31, // Usage (Y)
+    0x81, 0x02, // Input (Data,Var,Abs)
+    0xc0, // End Collection
+    0xc0, // End Collection
+];
+
+pub(crate) fn add(registration: &mut Registration<super::Token>, hid: &Hid) {
     registration.add_primary_service(srv::HUMAN_INTERFACE_DEVIC);
     registration.add_characteristic(
         ch::HID_INFORMATION,
         vec![0x10, 0x01, 0x00, 0x02],
         CharacteristicProperties::READ,
     );
-    registration.add_characteristic(
-        ch::REPORT_MAP,
-        vec![
-            0x05, 0x01, // Usage Page (Generic Desktop)
-            0x09, 0x06, // Usage (Keyboard)
-            0xa1, 0x01, // Collection (Application)
-            0x85, 0x01, // Report ID 0x01
-            0x05, 0x07, // Usage Page (Keyboard/Keypad)
-            0x19, 0xe0, // UsageMin (0xE0)
-            0x29, 0xe7, // UsageMax (0xE7)
-            0x14, // LogicalMin (0)
-            0x25, 0x01, // LogicalMax (1)
-            0x75, 0x01, // Report Size (1)
-            0x95, 0x08, // Report Count (8)
-            0x81, 0x02, // Input (Rel)
-            0x81, 0x03, // Input (Rel Var)
-            0x95, 0x05, // Report Count (5)
-            0x05, 0x08, // Usage Page (LED)
-            0x19, 0x01, // UsageMin (1)
-            0x29, 0x05, // UsageMax (5)
-            0x91, 0x02, // Output (Rel)
-            0x95, 0x01, // Report Count (1)
-            0x75, 0x03, // Report Size (3)
-            0x91, 0x01, // Output (Array)
-            0x95, 0x06, // Report Count (1)
-            0x75, 0x08, // Report Size (8)
-            0x14, // LogicalMin (0)
-            0x26, 0xa4, 0x00, // LogicalMax(0xA400)
-            0x05, 0x07, // Usage Page (Keyboard/Keypad)
-            0x18, // UsageMin (0)
-            0x29, 0xa4, // UsageMax (0xA4)
-            0x80, // ?
-            0xc0, // End Collection
-            0x05, 0x01, // Usage Page (Generic Desktop)
-            0x09, 0x02, // Usage (Mouse)
-            0xa1, 0x01, // Collection (Application)
-            0x85, 0x02, // Report ID
-            0x09, 0x01, // Usage (Pointer)
-            0xa0, 0x05, 0x09, // Usage Page (?)
-            0x19, 0x01, // UsageMin (1)
-            0x29, 0x03, // UsageMax (3)
-            0x14, // LogicalMin (0)
-            0x25, 0x01, // UsageMax (1)
-            0x95, 0x03, // Report Count
-            0x75, 0x01, // Report Size
-            0x81, 0x02, // Input (Rel)
-            0x95, 0x01, // Report Count (1)
-            0x75, 0x05, // Report Size (5)
-            0x81, 0x01, // Input (Var)
-            0x05, 0x01, // Usage Page (Generic Desktop)
-            0x15, 0x81, // LogicalMin (0x81)
-            0x25, 0x7f, // LogicalMax (0x7f)
-            0x75, 0x08, // Report Size (8)
-            0x95, 0x02, // Report Cont (2)
-            0x09, 0x30, // Usage ?
-            0x09, 0x31, // Usage ?
-            0x81, 0x06, // Input (Rel Wrap)
-            0x15, 0x81, // LogicalMin (0x81)
-            0x25, 0x7f, // LogicalMax (0x7f)
-            0x75, 0x08, // Report Size (8)
-            0x95, 0x01, // Report Count (1)
-            0x09, 0x38, // Usage (?)
-            0x81, 0x06, // Input (Rel Wrap)
-            0xc0, // End Collection
-            0xc0, // End Collection
-        ],
-        CharacteristicProperties::READ,
-    );
+    let mut report_map = vec![
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0x09, 0x06, // Usage (Keyboard)
+        0xa1, 0x01, // Collection (Application)
+        0x85, 0x01, // Report ID 0x01
+        0x05, 0x07, // Usage Page (Keyboard/Keypad)
+        0x19, 0xe0, // UsageMin (0xE0)
+        0x29, 0xe7, // UsageMax (0xE7)
+        0x14, // LogicalMin (0)
+        0x25, 0x01, // LogicalMax (1)
+        0x75, 0x01, // Report Size (1)
+        0x95, 0x08, // Report Count (8)
+        0x81, 0x02, // Input (Rel)
+        0x81, 0x03, // Input (Rel Var)
+        0x95, 0x05, // Report Count (5)
+        0x05, 0x08, // Usage Page (LED)
+        0x19, 0x01, // UsageMin (1)
+        0x29, 0x05, // UsageMax (5)
+        0x91, 0x02, // Output (Rel)
+        0x95, 0x01, // Report Count (1)
+        0x75, 0x03, // Report Size (3)
+        0x91, 0x01, // Output (Array)
+        0x95, 0x06, // Report Count (1)
+        0x75, 0x08, // Report Size (8)
+        0x14, // LogicalMin (0)
+        0x26, 0xa4, 0x00, // LogicalMax(0xA400)
+        0x05, 0x07, // Usage Page (Keyboard/Keypad)
+        0x18, // UsageMin (0)
+        0x29, 0xa4, // UsageMax (0xA4)
+        0x80, // ?
+        0xc0, // End Collection
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0x09, 0x02, // Usage (Mouse)
+        0xa1, 0x01, // Collection (Application)
+        0x85, 0x02, // Report ID
+        0x09, 0x01, // Usage (Pointer)
+        0xa0, 0x05, 0x09, // Usage Page (?)
+        0x19, 0x01, // UsageMin (1)
+        0x29, 0x08, // UsageMax (8)
+        0x14, // LogicalMin (0)
+        0x25, 0x01, // UsageMax (1)
+        0x95, 0x08, // Report Count
+        0x75, 0x01, // Report Size
+        0x81, 0x02, // Input (Rel)
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0x15, 0x81, // LogicalMin (0x81)
+        0x25, 0x7f, // LogicalMax (0x7f)
+        0x75, 0x08, // Report Size (8)
+        0x95, 0x02, // Report Cont (2)
+        0x09, 0x30, // Usage ?
+        0x09, 0x31, // Usage ?
+        0x81, 0x06, // Input (Rel Wrap)
+    ];
+    if hid.resolution_multiplier {
+        report_map.extend(HIRES_SCROLL_REPORT_MAP);
+    } else {
+        report_map.extend(SCROLL_REPORT_MAP);
+    }
+    report_map.extend([
+        0xc0, // End Collection
+        0xc0, // End Collection
+        0x05, 0x0c, // Usage Page (Consumer)
+        0x09, 0x01, // Usage (Consumer Control)
+        0xa1, 0x01, // Collection (Application)
+        0x85, 0x03, // Report ID 0x03
+        0x14, // LogicalMin (0)
+        0x26, 0xff, 0x03, // LogicalMax (0x03FF)
+        0x18, // UsageMin (0)
+        0x2a, 0xff, 0x03, // UsageMax (0x03FF)
+        0x75, 0x10, // Report Size (16)
+        0x95, 0x01, // Report Count (1)
+        0x80, // Input (Array)
+        0xc0, // End Collection
+    ]);
+    if hid.digitizer {
+        report_map.extend(DIGITIZER_REPORT_MAP);
+    }
+    if hid.nkro {
+        report_map.extend(NKRO_REPORT_MAP);
+    }
+    registration.add_characteristic(ch::REPORT_MAP, report_map, CharacteristicProperties::READ);
 
     registration.add_characteristic_with_token(
         super::Token::Keyboard,
@@ -86,21 +197,94 @@ pub(crate) fn add(registration: &mut Registration<super::Token>) {
     );
     registration.add_descriptor(Uuid::new_uuid16(0x2908), vec![0x01, 0x01], false);
 
+    registration.add_characteristic_with_token(
+        super::Token::KeyboardLed,
+        ch::REPORT,
+        vec![0x00],
+        CharacteristicProperties::READ
+            | CharacteristicProperties::WRITE
+            | CharacteristicProperties::WRITE_WITHOUT_RESPONSE,
+    );
+    registration.add_descriptor(Uuid::new_uuid16(0x2908), vec![0x01, 0x02], false);
+
+    if hid.nkro {
+        registration.add_characteristic_with_token(
+            super::Token::KeyboardNkro,
+            ch::REPORT,
+            vec![0x00; 29],
+            CharacteristicProperties::READ | CharacteristicProperties::NOTIFY,
+        );
+        registration.add_descriptor(Uuid::new_uuid16(0x2908), vec![0x05, 0x01], false);
+    }
+
     registration.add_characteristic_with_token(
         super::Token::Mouse,
         ch::REPORT,
-        vec![0x10, 0x01, 0x00, 0x00, 0x02],
+        vec![0x00; 5],
         CharacteristicProperties::READ | CharacteristicProperties::NOTIFY,
     );
     registration.add_descriptor(Uuid::new_uuid16(0x2908), vec![0x02, 0x01], false);
 
-    registration.add_characteristic(
+    if hid.resolution_multiplier {
+        registration.add_characteristic_with_token(
+            super::Token::MouseFeature,
+            ch::REPORT,
+            vec![0x00],
+            CharacteristicProperties::READ | CharacteristicProperties::WRITE,
+        );
+        registration.add_descriptor(Uuid::new_uuid16(0x2908), vec![0x02, 0x03], false);
+    }
+
+    registration.add_characteristic_with_token(
+        super::Token::Consumer,
+        ch::REPORT,
+        vec![0x00, 0x00],
+        CharacteristicProperties::READ | CharacteristicProperties::NOTIFY,
+    );
+    registration.add_descriptor(Uuid::new_uuid16(0x2908), vec![0x03, 0x01], false);
+
+    if hid.digitizer {
+        registration.add_characteristic_with_token(
+            super::Token::Digitizer,
+            ch::REPORT,
+            vec![0x00; 5],
+            CharacteristicProperties::READ | CharacteristicProperties::NOTIFY,
+        );
+        registration.add_descriptor(Uuid::new_uuid16(0x2908), vec![0x04, 0x01], false);
+    }
+
+    registration.add_characteristic_with_token(
+        super::Token::ProtocolMode,
         ch::PROTOCOL_MODE,
         vec![0x01],
         CharacteristicProperties::READ | CharacteristicProperties::WRITE_WITHOUT_RESPONSE,
     );
 
-    registration.add_characteristic(
+    registration.add_characteristic_with_token(
+        super::Token::BootKeyboard,
+        ch::BOOT_KEYBOARD_INPUT_REPORT,
+        vec![0x00; 8],
+        CharacteristicProperties::READ | CharacteristicProperties::NOTIFY,
+    );
+
+    registration.add_characteristic_with_token(
+        super::Token::BootKeyboardLed,
+        ch::BOOT_KEYBOARD_OUTPUT_REPORT,
+        vec![0x00],
+        CharacteristicProperties::READ
+            | CharacteristicProperties::WRITE
+            | CharacteristicProperties::WRITE_WITHOUT_RESPONSE,
+    );
+
+    registration.add_characteristic_with_token(
+        super::Token::BootMouse,
+        ch::BOOT_MOUSE_INPUT_REPORT,
+        vec![0x00; 3],
+        CharacteristicProperties::READ | CharacteristicProperties::NOTIFY,
+    );
+
+    registration.add_characteristic_with_token(
+        super::Token::ControlPoint,
         ch::HID_CONTROL_POINT,
         vec![],
         CharacteristicProperties::READ | CharacteristicProperties::WRITE_WITHOUT_RESPONSE,
@@ -1,5 +1,7 @@
 use ::gatt::Registration;
 
+use crate::config::Config;
+
 mod bas;
 mod dis;
 mod gap;
@@ -9,17 +11,28 @@ mod hids;
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) enum Token {
     Keyboard,
+    KeyboardLed,
+    KeyboardNkro,
     Mouse,
+    MouseFeature,
+    Consumer,
+    Digitizer,
+    ProtocolMode,
+    ControlPoint,
+    Battery,
+    BootKeyboard,
+    BootKeyboardLed,
+    BootMouse,
 }
 
-pub(crate) fn new() -> Registration<Token> {
+pub(crate) fn new(config: &Config, battery_level: u8) -> Registration<Token> {
     let mut registration = Registration::new();
 
-    gap::add(&mut registration);
+    gap::add(&mut registration, &config.device);
     gatt::add(&mut registration);
-    dis::add(&mut registration);
-    bas::add(&mut registration);
-    hids::add(&mut registration);
+    dis::add(&mut registration, &config.device);
+    bas::add(&mut registration, battery_level);
+    hids::add(&mut registration, &config.hid);
 
     registration
 }
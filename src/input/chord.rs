@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+
+use super::kbstat::{KbStat, MetaKeys};
+use crate::hid::{parse_key, KeyboardUsageId, UnknownUsage};
+
+/// Key combination like `ctrl+alt+f1`.
+///
+/// Modifiers match both of the left and right keys.
+#[derive(Debug, Clone)]
+pub struct Chord {
+    meta: MetaKeys,
+    keys: HashSet<KeyboardUsageId>,
+}
+
+impl Chord {
+    /// Report pressing the chord.
+    pub(crate) fn to_kbstat(&self) -> KbStat {
+        KbStat::from_keys(self.meta, self.keys.iter().cloned())
+    }
+
+    pub fn matches(&self, kbstat: &KbStat) -> bool {
+        let meta = kbstat.meta().bits();
+        let meta = MetaKeys::from_bits_truncate((meta | meta >> 4) & 0x0F);
+        meta == self.meta
+            && kbstat.keys().len() == self.keys.len()
+            && kbstat.keys().iter().all(|k| self.keys.contains(k))
+    }
+}
+
+impl FromStr for Chord {
+    type Err = UnknownUsage;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut meta = MetaKeys::empty();
+        let mut keys = HashSet::new();
+        for token in s.split('+').map(str::trim) {
+            match token.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => meta |= MetaKeys::LEFT_CTRL,
+                "shift" => meta |= MetaKeys::LEFT_SHIFT,
+                "alt" => meta |= MetaKeys::LEFT_ALT,
+                "gui" | "meta" | "super" => meta |= MetaKeys::LEFT_GUI,
+                _ => {
+                    keys.insert(parse_key(token)?);
+                }
+            }
+        }
+        Ok(Self { meta, keys })
+    }
+}
+
+impl<'de> Deserialize<'de> for Chord {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let chord = "ctrl+alt+delete".parse::<Chord>().unwrap();
+        let kbstat = chord.to_kbstat();
+        assert_eq!(kbstat.meta(), MetaKeys::LEFT_CTRL | MetaKeys::LEFT_ALT);
+        assert_eq!(kbstat.keys(), &[KeyboardUsageId::KEY_DELETE_FORWARD]);
+
+        let chord = "Ctrl + Backspace".parse::<Chord>().unwrap();
+        assert_eq!(chord.to_kbstat().keys(), &[KeyboardUsageId::KEY_DELETE]);
+        let chord = "ctrl+KEY_DELETE".parse::<Chord>().unwrap();
+        assert_eq!(chord.to_kbstat().keys(), &[KeyboardUsageId::KEY_DELETE]);
+
+        assert!("ctrl+nokey".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let chord = "ctrl+shift+f1".parse::<Chord>().unwrap();
+        let right = MetaKeys::RIGHT_CTRL | MetaKeys::LEFT_SHIFT;
+        assert!(chord.matches(&KbStat::from_key(right, KeyboardUsageId::KEY_F1)));
+        assert!(!chord.matches(&KbStat::from_key(
+            MetaKeys::LEFT_CTRL,
+            KeyboardUsageId::KEY_F1
+        )));
+    }
+}
@@ -0,0 +1,55 @@
+use std::convert::TryFrom;
+use std::io;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use btknmle_input::event::keyboard::KeyState;
+use btknmle_input::event::keyboard::KeyboardEventTrait as _;
+use btknmle_input::event::KeyboardEvent;
+use btknmle_input::KeyCodes;
+
+use crate::hid::ConsumerUsageId;
+
+#[derive(Debug, Clone)]
+pub struct ConsumerStat {
+    usages: Vec<ConsumerUsageId>,
+}
+
+impl ConsumerStat {
+    pub fn new() -> Self {
+        Self { usages: vec![] }
+    }
+
+    /// Returns false if the key is not on the Consumer page.
+    pub fn recv(&mut self, evt: &KeyboardEvent) -> bool {
+        let code = KeyCodes::from(evt.key());
+        let code = if let Ok(code) = ConsumerUsageId::try_from(code) {
+            code
+        } else {
+            return false;
+        };
+
+        match evt.key_state() {
+            KeyState::Pressed => {
+                if !self.usages.contains(&code) {
+                    self.usages.push(code);
+                }
+            }
+            KeyState::Released => self.usages.retain(|u| u != &code),
+        }
+        true
+    }
+
+    pub async fn write_to<W>(&self, write: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        // report the most recently pressed one.
+        let usage = self
+            .usages
+            .last()
+            .map(|v| u16::from(v.clone()))
+            .unwrap_or_default();
+        write.write_all(&usage.to_le_bytes()).await
+    }
+}
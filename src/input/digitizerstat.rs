@@ -0,0 +1,112 @@
+use std::io;
+
+use bitflags::bitflags;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use btknmle_input::event::pointer::PointerMotionAbsoluteEvent;
+use btknmle_input::event::tablet_tool::{ProximityState, TabletToolEventTrait as _, TipState};
+use btknmle_input::event::touch::{TouchEventPosition, TouchEventSlot as _};
+use btknmle_input::event::{TabletToolEvent, TouchEvent};
+
+/// Logical maximum of X and Y in the report map.
+pub const LOGICAL_MAX: u16 = 0x7FFF;
+
+bitflags! {
+    pub struct State: u8 {
+        const TIP_SWITCH = 0b0000_0001;
+        const IN_RANGE = 0b0000_0010;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DigitizerStat {
+    state: State,
+    x: u16,
+    y: u16,
+    /// Only the first finger is tracked.
+    touch_slot: Option<u32>,
+}
+
+impl DigitizerStat {
+    pub fn new() -> Self {
+        Self {
+            state: State::empty(),
+            x: 0,
+            y: 0,
+            touch_slot: None,
+        }
+    }
+
+    fn set_position<E>(&mut self, evt: &E)
+    where
+        E: TouchEventPosition,
+    {
+        self.x = evt.x_transformed(LOGICAL_MAX.into()) as u16;
+        self.y = evt.y_transformed(LOGICAL_MAX.into()) as u16;
+    }
+
+    pub fn recv_motion_absolute(&mut self, evt: &PointerMotionAbsoluteEvent) {
+        self.state = State::IN_RANGE;
+        self.x = evt.absolute_x_transformed(LOGICAL_MAX.into()) as u16;
+        self.y = evt.absolute_y_transformed(LOGICAL_MAX.into()) as u16;
+    }
+
+    /// Returns false if the event is not for the tracked finger.
+    pub fn recv_touch(&mut self, evt: &TouchEvent) -> bool {
+        match evt {
+            TouchEvent::Down(evt) if self.touch_slot.is_none() => {
+                self.touch_slot = Some(evt.seat_slot());
+                self.state = State::TIP_SWITCH | State::IN_RANGE;
+                self.set_position(evt);
+            }
+            TouchEvent::Motion(evt) if self.touch_slot == Some(evt.seat_slot()) => {
+                self.set_position(evt);
+            }
+            TouchEvent::Up(evt) if self.touch_slot == Some(evt.seat_slot()) => {
+                self.touch_slot = None;
+                self.state = State::empty();
+            }
+            TouchEvent::Cancel(evt) if self.touch_slot == Some(evt.seat_slot()) => {
+                self.touch_slot = None;
+                self.state = State::empty();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Returns false if nothing changed.
+    pub fn recv_tablet_tool(&mut self, evt: &TabletToolEvent) -> bool {
+        match evt {
+            TabletToolEvent::Axis(evt) => {
+                self.x = evt.x_transformed(LOGICAL_MAX.into()) as u16;
+                self.y = evt.y_transformed(LOGICAL_MAX.into()) as u16;
+            }
+            TabletToolEvent::Proximity(evt) => match evt.proximity_state() {
+                ProximityState::In => {
+                    self.state = State::IN_RANGE;
+                    self.x = evt.x_transformed(LOGICAL_MAX.into()) as u16;
+                    self.y = evt.y_transformed(LOGICAL_MAX.into()) as u16;
+                }
+                ProximityState::Out => self.state = State::empty(),
+            },
+            TabletToolEvent::Tip(evt) => match evt.tip_state() {
+                TipState::Down => self.state |= State::TIP_SWITCH,
+                TipState::Up => self.state -= State::TIP_SWITCH,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    pub async fn write_to<W>(&self, write: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut b = [0; 5];
+        b[0] = self.state.bits();
+        b[1..3].copy_from_slice(&self.x.min(LOGICAL_MAX).to_le_bytes());
+        b[3..5].copy_from_slice(&self.y.min(LOGICAL_MAX).to_le_bytes());
+        write.write_all(&b).await
+    }
+}
@@ -1,4 +1,3 @@
-use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::io;
 
@@ -8,6 +7,7 @@ use tokio::io::{AsyncWrite, AsyncWriteExt};
 use btknmle_input::event::keyboard::KeyState;
 use btknmle_input::event::keyboard::KeyboardEventTrait as _;
 use btknmle_input::event::KeyboardEvent;
+use btknmle_input::model::Led;
 use btknmle_input::KeyCodes;
 
 use crate::hid::KeyboardUsageId;
@@ -25,6 +25,32 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct Leds: u8 {
+        const NUM_LOCK = 0b0000_0001;
+        const CAPS_LOCK = 0b0000_0010;
+        const SCROLL_LOCK = 0b0000_0100;
+        const COMPOSE = 0b0000_1000;
+        const KANA = 0b0001_0000;
+    }
+}
+
+impl From<Leds> for Led {
+    fn from(v: Leds) -> Self {
+        let mut led = Led::empty();
+        if v.contains(Leds::NUM_LOCK) {
+            led |= Led::NUMLOCK;
+        }
+        if v.contains(Leds::CAPS_LOCK) {
+            led |= Led::CAPSLOCK;
+        }
+        if v.contains(Leds::SCROLL_LOCK) {
+            led |= Led::SCROLLLOCK;
+        }
+        led
+    }
+}
+
 impl MetaKeys {
     fn from_keycodes(k: &KeyboardUsageId) -> Option<Self> {
         Some(match k {
@@ -39,12 +65,20 @@ impl MetaKeys {
             _ => return None,
         })
     }
+
+    pub(crate) fn is_modifier(k: &KeyboardUsageId) -> bool {
+        Self::from_keycodes(k).is_some()
+    }
 }
 
+/// Size of the NKRO bitmap. (one bit per usage 0x00-0xE7)
+const NKRO_BITMAP_LEN: usize = 0xE8 / 8;
+
 #[derive(Debug, Clone)]
 pub struct KbStat {
     meta: MetaKeys,
-    keys: HashSet<KeyboardUsageId>,
+    /// In the pressed order.
+    keys: Vec<KeyboardUsageId>,
 }
 
 impl KbStat {
@@ -55,48 +89,82 @@ impl KbStat {
         }
     }
 
-    pub fn keys(&self) -> &HashSet<KeyboardUsageId> {
+    /// Report with a single key pressed.
+    pub fn from_key(meta: MetaKeys, key: KeyboardUsageId) -> Self {
+        Self::from_keys(meta, [key])
+    }
+
+    pub fn from_keys<I>(meta: MetaKeys, keys: I) -> Self
+    where
+        I: IntoIterator<Item = KeyboardUsageId>,
+    {
+        Self {
+            meta,
+            keys: keys.into_iter().collect(),
+        }
+    }
+
+    pub fn meta(&self) -> MetaKeys {
+        self.meta
+    }
+
+    pub fn keys(&self) -> &[KeyboardUsageId] {
         &self.keys
     }
 
-    pub fn recv(&mut self, evt: &KeyboardEvent) {
-        let code = KeyCodes::from(evt.key());
-        let code = KeyboardUsageId::try_from(code);
-        if let Ok(code) = code {
-            match evt.key_state() {
-                KeyState::Pressed => {
-                    if let Some(meta) = MetaKeys::from_keycodes(&code) {
-                        self.meta |= meta;
-                    } else {
-                        self.keys.insert(code);
-                    }
-                }
-                KeyState::Released => {
-                    if let Some(meta) = MetaKeys::from_keycodes(&code) {
-                        self.meta -= meta;
-                    } else {
-                        self.keys.remove(&code);
-                    }
-                }
+    /// Usage of the key and whether it is pressed. None if no usage for the key.
+    pub fn key_of(evt: &KeyboardEvent) -> Option<(KeyboardUsageId, bool)> {
+        let code = KeyboardUsageId::try_from(KeyCodes::from(evt.key())).ok()?;
+        Some((code, evt.key_state() == KeyState::Pressed))
+    }
+
+    pub fn recv(&mut self, code: KeyboardUsageId, pressed: bool) {
+        if pressed {
+            if let Some(meta) = MetaKeys::from_keycodes(&code) {
+                self.meta |= meta;
+            } else if !self.keys.contains(&code) {
+                self.keys.push(code);
             }
+        } else if let Some(meta) = MetaKeys::from_keycodes(&code) {
+            self.meta -= meta;
+        } else {
+            self.keys.retain(|k| k != &code);
         }
     }
 
+    /// Write 6KRO report. (same as the boot keyboard report)
+    ///
+    /// Reports ErrorRollOver if more than six keys are pressed.
     pub async fn write_to<W>(&self, write: &mut W) -> io::Result<()>
     where
         W: AsyncWrite + Unpin,
     {
-        let mut keys = self.keys.iter().map(|v| v.clone().into());
-        let b = [
-            self.meta.bits(),
-            0x00,
-            keys.next().unwrap_or_default(),
-            keys.next().unwrap_or_default(),
-            keys.next().unwrap_or_default(),
-            keys.next().unwrap_or_default(),
-            keys.next().unwrap_or_default(),
-            keys.next().unwrap_or_default(),
-        ];
+        let mut b = [0; 8];
+        b[0] = self.meta.bits();
+        if self.keys.len() > 6 {
+            b[2..].fill(KeyboardUsageId::KEY_ERROR_ROLLOVER.into());
+        } else {
+            for (slot, key) in b[2..].iter_mut().zip(&self.keys) {
+                *slot = key.clone().into();
+            }
+        }
+        write.write_all(&b).await
+    }
+
+    /// Write NKRO bitmap report.
+    pub async fn write_nkro_to<W>(&self, write: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut b = [0; NKRO_BITMAP_LEN];
+        for key in &self.keys {
+            let usage = u8::from(key.clone()) as usize;
+            if usage < 0xE0 {
+                b[usage / 8] |= 1 << (usage % 8);
+            }
+        }
+        // 0xE0-0xE7
+        b[NKRO_BITMAP_LEN - 1] = self.meta.bits();
         write.write_all(&b).await
     }
 }
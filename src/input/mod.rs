@@ -1,5 +1,14 @@
+pub use chord::Chord;
+pub use mousestat::Scroll;
 pub use source::*;
+pub use text::Layout;
 
+mod chord;
+mod consumerstat;
+mod digitizerstat;
 pub mod kbstat;
 mod mousestat;
+mod remap;
+mod rules;
 mod source;
+mod text;
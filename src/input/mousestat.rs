@@ -9,18 +9,75 @@ use btknmle_input::event::pointer::{
 use btknmle_input::ButtonCodes;
 
 bitflags! {
+    /// Button 1-8 in the same order as Linux maps HID buttons.
     pub struct Button: u8 {
         const LEFT = 0b0000_0001;
         const RIGHT = 0b0000_0010;
         const MIDDLE = 0b0000_0100;
+        const SIDE = 0b0000_1000;
+        const EXTRA = 0b0001_0000;
+        const FORWARD = 0b0010_0000;
+        const BACK = 0b0100_0000;
+        const TASK = 0b1000_0000;
     }
 }
 
+/// Wheel and AC Pan resolution when the host enables the Resolution Multiplier.
+pub const HIRES_MULTIPLIER: f64 = 8.0;
+
+/// Scroll amount per detent in libinput.
+const DEGREES_PER_DETENT: f64 = 15.0;
+/// Wheel units per detent. (three lines on most hosts)
+const UNITS_PER_DETENT: f64 = 3.0;
+
 #[derive(Debug, Clone)]
 pub enum Value {
     None,
     Move(f64, f64),
-    Wheel(f64),
+    /// Vertical and horizontal in wheel units.
+    Scroll(f64, f64),
+}
+
+/// Scroll state for each host.
+///
+/// Keeps the fraction not reported yet.
+#[derive(Debug, Default)]
+pub struct Scroll {
+    hires_wheel: bool,
+    hires_pan: bool,
+    wheel: f64,
+    pan: f64,
+}
+
+impl Scroll {
+    /// Receive the Resolution Multiplier feature report.
+    pub fn set_resolution_multiplier(&mut self, feature: u8) {
+        self.hires_wheel = feature & 0b0000_0011 != 0;
+        self.hires_pan = feature & 0b0000_1100 != 0;
+    }
+
+    fn accumulate(acc: &mut f64, value: f64, hires: bool) -> i8 {
+        let value = if hires {
+            value * HIRES_MULTIPLIER
+        } else {
+            value
+        };
+        if value != 0.0 && *acc * value < 0.0 {
+            // drop the fraction on direction change. (not on the events without this axis)
+            *acc = 0.0;
+        }
+        *acc += value;
+        let n = acc.trunc().clamp(-127.0, 127.0);
+        *acc -= n;
+        n as i8
+    }
+
+    fn take(&mut self, wheel: f64, pan: f64) -> (i8, i8) {
+        (
+            Self::accumulate(&mut self.wheel, wheel, self.hires_wheel),
+            Self::accumulate(&mut self.pan, pan, self.hires_pan),
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -46,27 +103,65 @@ impl MouseStat {
             ButtonCodes::BTN_LEFT => Button::LEFT,
             ButtonCodes::BTN_RIGHT => Button::RIGHT,
             ButtonCodes::BTN_MIDDLE => Button::MIDDLE,
+            ButtonCodes::BTN_SIDE => Button::SIDE,
+            ButtonCodes::BTN_EXTRA => Button::EXTRA,
+            ButtonCodes::BTN_FORWARD => Button::FORWARD,
+            ButtonCodes::BTN_BACK => Button::BACK,
+            ButtonCodes::BTN_TASK => Button::TASK,
             _ => return,
         };
 
-        match evt.button_state() {
-            ButtonState::Pressed => self.button |= button,
-            ButtonState::Released => self.button -= button,
+        self.press(button, evt.button_state() == ButtonState::Pressed);
+    }
+
+    fn press(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.button |= button;
+        } else {
+            self.button -= button;
         }
+        // the button report never repeats the last motion or scroll.
+        self.value = Value::None;
     }
 
     pub fn recv_axis(&mut self, evt: &PointerAxisEvent) {
-        if evt.has_axis(Axis::Vertical) {
-            self.value = Value::Wheel(evt.axis_value(Axis::Vertical) * 0.1)
-        }
+        let value = |axis| {
+            if evt.has_axis(axis) {
+                evt.axis_value(axis) / DEGREES_PER_DETENT * UNITS_PER_DETENT
+            } else {
+                0.0
+            }
+        };
+        // only horizontal is negated. vertical keeps the sign sent before.
+        self.scroll(value(Axis::Vertical), -value(Axis::Horizontal));
+    }
+
+    fn scroll(&mut self, wheel: f64, pan: f64) {
+        self.value = Value::Scroll(wheel, pan);
+    }
+
+    /// Write boot mouse report. (3 buttons, no wheel)
+    pub async fn write_boot_to<W>(&self, write: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let button = (self.button & (Button::LEFT | Button::RIGHT | Button::MIDDLE)).bits();
+        let b = match &self.value {
+            Value::Move(dx, dy) => {
+                const MUL: f64 = 1.5;
+                [button, (*dx * MUL) as i8 as _, (*dy * MUL) as i8 as _]
+            }
+            Value::None | Value::Scroll(..) => [button, 0x00, 0x00],
+        };
+        write.write_all(&b).await
     }
 
-    pub async fn write_to<W>(&self, write: &mut W) -> io::Result<()>
+    pub async fn write_to<W>(&self, write: &mut W, scroll: &mut Scroll) -> io::Result<()>
     where
         W: AsyncWrite + Unpin,
     {
         let b = match &self.value {
-            Value::None => [self.button.bits(), 0x00, 0x00, 0x00],
+            Value::None => [self.button.bits(), 0x00, 0x00, 0x00, 0x00],
             Value::Move(dx, dy) => {
                 const MUL: f64 = 1.5;
                 [
@@ -74,13 +169,65 @@ impl MouseStat {
                     (*dx * MUL) as i8 as _,
                     (*dy * MUL) as i8 as _,
                     0x00,
+                    0x00,
                 ]
             }
-            Value::Wheel(z) => {
-                const MUL: f64 = 2.0;
-                [self.button.bits(), 0x00, 0x00, (*z * MUL) as i8 as _]
+            Value::Scroll(wheel, pan) => {
+                let (wheel, pan) = scroll.take(*wheel, *pan);
+                [self.button.bits(), 0x00, 0x00, wheel as _, pan as _]
             }
         };
         write.write_all(&b).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulate() {
+        let mut scroll = Scroll::default();
+        assert_eq!(scroll.take(0.5, 0.0), (0, 0));
+        // pan only. (-0.0 wheel)
+        assert_eq!(scroll.take(-0.0, 0.5), (0, 0));
+        assert_eq!(scroll.take(0.5, -0.0), (1, 0));
+        assert_eq!(scroll.take(0.0, 0.5), (0, 1));
+
+        assert_eq!(scroll.take(-0.5, -0.5), (0, 0));
+        assert_eq!(scroll.take(0.0, -0.0), (0, 0));
+        assert_eq!(scroll.take(-0.5, -0.5), (-1, -1));
+    }
+
+    #[test]
+    fn test_accumulate_direction_change() {
+        let mut scroll = Scroll::default();
+        assert_eq!(scroll.take(0.75, 0.0), (0, 0));
+        // the fraction is dropped.
+        assert_eq!(scroll.take(-0.5, 0.0), (0, 0));
+        assert_eq!(scroll.take(-0.5, 0.0), (-1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_click_after_scroll() {
+        let mut stat = MouseStat::new();
+        let mut scroll = Scroll::default();
+        let mut buf = vec![];
+        stat.scroll(UNITS_PER_DETENT, 0.0);
+        stat.write_to(&mut buf, &mut scroll).await.unwrap();
+        assert_eq!(buf, [0x00, 0x00, 0x00, 0x03, 0x00]);
+
+        buf.clear();
+        stat.press(Button::LEFT, true);
+        stat.write_to(&mut buf, &mut scroll).await.unwrap();
+        assert_eq!(buf, [0x01, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_accumulate_hires() {
+        let mut scroll = Scroll::default();
+        scroll.set_resolution_multiplier(0b0000_0001);
+        assert_eq!(scroll.take(0.5, 0.5), (4, 0));
+        assert_eq!(scroll.take(0.0, 0.5), (0, 1));
+    }
+}
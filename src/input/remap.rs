@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use btknmle_input::model::Device;
+
+use super::rules;
+use crate::config::{LayerMode, Remap as RemapConfig};
+use crate::hid::KeyboardUsageId;
+
+type Keys = HashMap<KeyboardUsageId, KeyboardUsageId>;
+
+/// Translates the keys before [`KbStat`](super::kbstat::KbStat).
+#[derive(Debug)]
+pub(crate) struct Remap {
+    config: RemapConfig,
+    /// sysname -> keys merged with the matching device rules.
+    devices: HashMap<String, Keys>,
+    /// Active layers. (index of `config.layer`, last activated last)
+    layers: Vec<usize>,
+    /// (sysname, pressed key) -> reported key. The release is reported as the same key
+    /// even if the layer changed while pressed.
+    pressed: HashMap<(String, KeyboardUsageId), KeyboardUsageId>,
+}
+
+impl Remap {
+    pub(crate) fn new(config: RemapConfig) -> Self {
+        Self {
+            config,
+            devices: Default::default(),
+            layers: vec![],
+            pressed: Default::default(),
+        }
+    }
+
+    pub(crate) fn add_device(&mut self, device: &Device) {
+        let mut keys = self.config.keys.clone();
+        for remap in &self.config.device {
+            if rules::matches(&remap.rule, device) {
+                keys.extend(remap.keys.clone());
+            }
+        }
+        self.devices.insert(device.sysname().to_string(), keys);
+    }
+
+    pub(crate) fn remove_device(&mut self, device: &Device) {
+        let sysname = device.sysname();
+        self.devices.remove(sysname);
+        self.pressed.retain(|(s, _), _| s != sysname);
+    }
+
+    /// Returns None if the key is consumed. (layer keys)
+    pub(crate) fn map(
+        &mut self,
+        device: &Device,
+        key: KeyboardUsageId,
+        pressed: bool,
+    ) -> Option<KeyboardUsageId> {
+        self.map_key(device.sysname(), key, pressed)
+    }
+
+    fn map_key(
+        &mut self,
+        sysname: &str,
+        key: KeyboardUsageId,
+        pressed: bool,
+    ) -> Option<KeyboardUsageId> {
+        if let Some(index) = self.config.layer.iter().position(|l| l.key == key) {
+            let activate = match self.config.layer[index].mode {
+                LayerMode::Toggle if pressed => !self.layers.contains(&index),
+                LayerMode::Toggle => return None,
+                LayerMode::Hold => pressed,
+            };
+            self.layers.retain(|i| *i != index);
+            if activate {
+                self.layers.push(index);
+            }
+            log::debug!("layer {} active: {}", index, activate);
+            return None;
+        }
+
+        let sysname = sysname.to_string();
+        if !pressed {
+            return Some(self.pressed.remove(&(sysname, key.clone())).unwrap_or(key));
+        }
+
+        let layers = &self.config.layer;
+        let mapped = self
+            .layers
+            .iter()
+            .rev()
+            .find_map(|i| layers[*i].keys.get(&key))
+            .or_else(|| match self.devices.get(&sysname) {
+                Some(keys) => keys.get(&key),
+                None => self.config.keys.get(&key),
+            })
+            .unwrap_or(&key)
+            .clone();
+        self.pressed.insert((sysname, key), mapped.clone());
+        Some(mapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyboardUsageId as K;
+
+    fn remap(config: &str) -> Remap {
+        let mut remap = Remap::new(toml::from_str(config).unwrap());
+        // as add_device without the matching device rules.
+        remap
+            .devices
+            .insert("event0".into(), remap.config.keys.clone());
+        remap
+    }
+
+    fn tap(remap: &mut Remap, key: K) -> Option<K> {
+        let pressed = remap.map_key("event0", key.clone(), true);
+        let released = remap.map_key("event0", key, false);
+        assert_eq!(pressed, released);
+        pressed
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut remap = remap(
+            r#"
+            [keys]
+            capslock = "left_ctrl"
+            "#,
+        );
+        assert_eq!(tap(&mut remap, K::KEY_CAPSLOCK), Some(K::KEY_LEFT_CTRL));
+        assert_eq!(tap(&mut remap, K::KEY_A), Some(K::KEY_A));
+        // not added device.
+        assert_eq!(
+            remap.map_key("event1", K::KEY_CAPSLOCK, true),
+            Some(K::KEY_LEFT_CTRL)
+        );
+    }
+
+    #[test]
+    fn test_toggle_layer() {
+        let mut remap = remap(
+            r#"
+            [[layer]]
+            key = "scrolllock"
+            keys = { h = "left" }
+            "#,
+        );
+        assert_eq!(tap(&mut remap, K::KEY_H), Some(K::KEY_H));
+        assert_eq!(tap(&mut remap, K::KEY_SCROLLLOCK), None);
+        assert_eq!(tap(&mut remap, K::KEY_H), Some(K::KEY_LEFT));
+        assert_eq!(tap(&mut remap, K::KEY_SCROLLLOCK), None);
+        assert_eq!(tap(&mut remap, K::KEY_H), Some(K::KEY_H));
+    }
+
+    #[test]
+    fn test_hold_layer() {
+        let mut remap = remap(
+            r#"
+            [[layer]]
+            key = "right_alt"
+            mode = "hold"
+            keys = { h = "left" }
+            "#,
+        );
+        assert_eq!(remap.map_key("event0", K::KEY_RIGHT_ALT, true), None);
+        assert_eq!(remap.map_key("event0", K::KEY_H, true), Some(K::KEY_LEFT));
+        assert_eq!(remap.map_key("event0", K::KEY_RIGHT_ALT, false), None);
+        // released as pressed.
+        assert_eq!(remap.map_key("event0", K::KEY_H, false), Some(K::KEY_LEFT));
+        assert_eq!(tap(&mut remap, K::KEY_H), Some(K::KEY_H));
+    }
+}
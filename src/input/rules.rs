@@ -0,0 +1,53 @@
+use std::ffi::OsStr;
+
+use btknmle_input::model::Device;
+
+use crate::config::{DeviceRule, Input};
+
+/// All specified conditions must match.
+pub(crate) fn matches(rule: &DeviceRule, device: &Device) -> bool {
+    if let Some(name) = &rule.name {
+        if device.name() != name {
+            return false;
+        }
+    }
+    if let Some(vendor_id) = rule.vendor_id {
+        if device.id_vendor() != vendor_id {
+            return false;
+        }
+    }
+    if let Some(product_id) = rule.product_id {
+        if device.id_product() != product_id {
+            return false;
+        }
+    }
+    if rule.syspath.is_none() && rule.property.is_empty() {
+        return true;
+    }
+
+    // udev device is only used while the libinput device is alive.
+    let udev = if let Some(udev) = unsafe { device.udev_device() } {
+        udev
+    } else {
+        return false;
+    };
+    if let Some(syspath) = &rule.syspath {
+        if !udev.syspath().starts_with(syspath) {
+            return false;
+        }
+    }
+    rule.property
+        .iter()
+        .all(|(key, value)| udev.property_value(key) == Some(OsStr::new(value)))
+}
+
+pub(crate) fn matches_any(rules: &[DeviceRule], device: &Device) -> bool {
+    rules.iter().any(|rule| matches(rule, device))
+}
+
+/// Returns true if the device matches any of the allow rules (or no allow rules)
+/// and none of the deny rules.
+pub(crate) fn is_allowed(config: &Input, device: &Device) -> bool {
+    (config.allow.is_empty() || matches_any(&config.allow, device))
+        && !matches_any(&config.deny, device)
+}
@@ -1,8 +1,10 @@
-use std::future::Future;
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
 
-use futures_channel::mpsc;
+use bdaddr::Address;
+use futures_channel::{mpsc, oneshot};
+use futures_util::future::{Fuse, FusedFuture as _};
 use futures_util::lock::{Mutex, MutexGuard};
 use futures_util::{select, FutureExt as _, StreamExt as _};
 
@@ -10,16 +12,26 @@ use btknmle_input::event::DeviceEvent;
 use btknmle_input::event::Event as LibinputEvent;
 use btknmle_input::event::EventTrait as _;
 use btknmle_input::event::PointerEvent;
-use btknmle_input::model::{Device, DeviceCapability};
+use btknmle_input::model::{Device, DeviceCapability, SendEventsMode};
 use btknmle_input::LibinputStream;
 
-use super::kbstat::KbStat;
+use super::chord::Chord;
+use super::consumerstat::ConsumerStat;
+use super::digitizerstat::DigitizerStat;
+use super::kbstat::{KbStat, Leds, MetaKeys};
 use super::mousestat::MouseStat;
+use super::remap::Remap;
+use super::rules;
+use super::text::{self, Layout};
+use crate::config::{GrabPolicy, Input as InputConfig, Remap as RemapConfig};
+use crate::monitor::Monitor;
 
 #[derive(Debug, Clone)]
 pub enum InputEvent {
     Keyboard(KbStat),
+    Consumer(ConsumerStat),
     Mouse(MouseStat),
+    Digitizer(DigitizerStat),
 }
 
 impl From<KbStat> for InputEvent {
@@ -28,12 +40,24 @@ impl From<KbStat> for InputEvent {
     }
 }
 
+impl From<ConsumerStat> for InputEvent {
+    fn from(v: ConsumerStat) -> Self {
+        Self::Consumer(v)
+    }
+}
+
 impl From<MouseStat> for InputEvent {
     fn from(v: MouseStat) -> Self {
         Self::Mouse(v)
     }
 }
 
+impl From<DigitizerStat> for InputEvent {
+    fn from(v: DigitizerStat) -> Self {
+        Self::Digitizer(v)
+    }
+}
+
 fn configure_device(device: &mut Device) {
     if device.has_capability(DeviceCapability::Gesture) {
         if let Err(e) = device.config_tap_set_enabled(true) {
@@ -61,21 +85,265 @@ impl<'a> Drop for InputStream<'a> {
     }
 }
 
+#[derive(Debug)]
+pub(crate) struct HostStream {
+    slot: usize,
+    slots: Arc<StdMutex<Vec<bool>>>,
+    rx: mpsc::UnboundedReceiver<InputEvent>,
+    control_tx: mpsc::UnboundedSender<Control>,
+}
+
+impl HostStream {
+    pub(crate) fn slot(&self) -> usize {
+        self.slot
+    }
+
+    pub(crate) async fn next(&mut self) -> Option<InputEvent> {
+        self.rx.next().await
+    }
+
+    /// Release the grab while the host is suspended.
+    pub(crate) fn set_suspended(&self, suspended: bool) -> anyhow::Result<()> {
+        self.control_tx
+            .unbounded_send(Control::SuspendHost(self.slot, suspended))?;
+        Ok(())
+    }
+}
+
+impl Drop for HostStream {
+    fn drop(&mut self) {
+        self.control_tx
+            .unbounded_send(Control::RemoveHost(self.slot))
+            .ok();
+        if let Ok(mut slots) = self.slots.lock() {
+            slots[self.slot] = false;
+        }
+    }
+}
+
 #[derive(Debug)]
 enum Control {
     BeginSubscribe(mpsc::UnboundedSender<InputEvent>),
     EndSubscribe,
+    AddHost(usize, Address, mpsc::UnboundedSender<InputEvent>),
+    RemoveHost(usize),
+    SwitchHost(usize, oneshot::Sender<Result<(), HostError>>),
+    SuspendHost(usize, bool),
+    Leds(Leds),
+    /// Shown instead of the host LEDs until `None`. (e.g. passkey input)
+    LocalLeds(Option<Leds>),
+    /// Notified of the key presses. The input still goes to the hosts.
+    ObserveKeys(mpsc::UnboundedSender<()>),
+    /// Not from the devices. (e.g. typing text)
+    Inject(InputEvent, oneshot::Sender<Result<(), HostError>>),
+}
+
+/// Why the active host was not reached or switched.
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum HostError {
+    #[error("no host connected")]
+    NoHost,
+    #[error("input is detached from the hosts")]
+    Detached,
+    #[error("input is captured by passkey entry")]
+    Captured,
+}
+
+#[derive(Debug)]
+struct Subscribers {
+    exclusive: Option<mpsc::UnboundedSender<InputEvent>>,
+    hosts: Vec<Option<mpsc::UnboundedSender<InputEvent>>>,
+    /// Hosts suspended by HID Control Point.
+    suspended: Vec<bool>,
+    active: usize,
+    /// Detached by the escape chord.
+    detached: bool,
+    /// Notified of the key presses. (e.g. waiting to restart advertising)
+    observers: Vec<mpsc::UnboundedSender<()>>,
+}
+
+impl Subscribers {
+    fn new(slots: usize) -> Self {
+        Self {
+            exclusive: None,
+            hosts: vec![None; slots],
+            suspended: vec![false; slots],
+            active: 0,
+            detached: false,
+            observers: vec![],
+        }
+    }
+
+    fn key_pressed(&mut self) {
+        self.observers.retain(|tx| tx.unbounded_send(()).is_ok());
+    }
+
+    /// Input is needed by the exclusive stream or any awake host.
+    fn wants_grab(&self) -> bool {
+        self.exclusive.is_some()
+            || self
+                .hosts
+                .iter()
+                .zip(&self.suspended)
+                .any(|(host, suspended)| host.is_some() && !suspended)
+    }
+
+    fn send(&mut self, event: InputEvent) {
+        if self.detached {
+            return;
+        }
+        let tx = if self.exclusive.is_some() {
+            &mut self.exclusive
+        } else {
+            &mut self.hosts[self.active]
+        };
+        if let Some(inner) = tx.as_mut() {
+            if let Err(err) = inner.unbounded_send(event) {
+                if err.is_disconnected() {
+                    *tx = None;
+                }
+            }
+        }
+    }
+
+    /// Send to the active host only. Never to the exclusive subscriber.
+    fn inject(&mut self, event: InputEvent) -> Result<(), HostError> {
+        if self.detached {
+            return Err(HostError::Detached);
+        }
+        if self.exclusive.is_some() {
+            return Err(HostError::Captured);
+        }
+        let tx = &mut self.hosts[self.active];
+        match tx.as_ref().map(|inner| inner.unbounded_send(event)) {
+            Some(Ok(())) => Ok(()),
+            Some(Err(..)) => {
+                *tx = None;
+                Err(HostError::NoHost)
+            }
+            None => Err(HostError::NoHost),
+        }
+    }
+
+    fn add_host(&mut self, slot: usize, tx: mpsc::UnboundedSender<InputEvent>) {
+        self.hosts[slot] = Some(tx);
+        if self.hosts[self.active].is_none() {
+            self.active = slot;
+        }
+    }
+
+    fn remove_host(&mut self, slot: usize) {
+        self.hosts[slot] = None;
+        self.suspended[slot] = false;
+        if slot == self.active {
+            if let Some(next) = self.hosts.iter().position(Option::is_some) {
+                log::info!("switch to host {}.", next + 1);
+                self.active = next;
+            }
+        }
+    }
+
+    /// The hosts stay connected. Reattaching resumes them without reconnecting,
+    /// and a host can still be dropped by the `disconnect` request.
+    /// Release all keys and buttons on the receiver.
+    fn release_all(&mut self) {
+        self.send(KbStat::new().into());
+        self.send(ConsumerStat::new().into());
+        self.send(MouseStat::new().into());
+    }
+
+    fn toggle_detached(&mut self) {
+        if !self.detached {
+            // release all keys before the input stops.
+            self.release_all();
+            log::info!("detached from the hosts. hold the escape chord again to reattach.");
+        } else {
+            log::info!("reattached to the hosts.");
+        }
+        self.detached = !self.detached;
+    }
+
+    fn active_host(&self) -> Option<usize> {
+        self.hosts[self.active].as_ref().map(|_| self.active)
+    }
+
+    /// Fails if the input is captured exclusively (e.g. passkey input) or no host is in the slot.
+    fn switch_host(&mut self, slot: usize) -> Result<(), HostError> {
+        if self.exclusive.is_some() {
+            return Err(HostError::Captured);
+        }
+        if !matches!(self.hosts.get(slot), Some(Some(..))) {
+            log::info!("no host connected for slot {}.", slot + 1);
+            return Err(HostError::NoHost);
+        }
+        if slot == self.active {
+            return Ok(());
+        }
+
+        // release all keys and buttons on the previous host.
+        self.release_all();
+        log::info!("switch to host {}.", slot + 1);
+        self.active = slot;
+        Ok(())
+    }
+}
+
+/// Input loop restartable by [`InputLoop::run`]. The subscribers are kept across the restarts.
+#[derive(Debug)]
+pub(crate) struct InputLoop {
+    control_rx: mpsc::UnboundedReceiver<Control>,
+    config: InputConfig,
+    remap: RemapConfig,
+    host_switch: Vec<Chord>,
+    pairing: Option<(Chord, mpsc::UnboundedSender<()>)>,
+    monitor: Monitor,
+    subscribers: Subscribers,
 }
 
-async fn input_loop(
-    mut control_rx: mpsc::UnboundedReceiver<Control>,
-    grab: bool,
-) -> anyhow::Result<()> {
-    let mut libinput = LibinputStream::new_from_udev("seat0")?; // TODO seat name
+impl InputLoop {
+    pub(crate) async fn run(&mut self) -> anyhow::Result<()> {
+        input_loop(self).await
+    }
+}
+
+async fn input_loop(state: &mut InputLoop) -> anyhow::Result<()> {
+    let InputLoop {
+        control_rx,
+        config,
+        remap,
+        host_switch,
+        pairing,
+        monitor,
+        subscribers,
+    } = state;
+    // release the keys pressed before the restart.
+    subscribers.release_all();
+
+    let mut remap = Remap::new(remap.clone());
+    let mut libinput = if config.paths.is_empty() {
+        LibinputStream::new_from_udev(&config.seat)?
+    } else {
+        let mut libinput = LibinputStream::new_from_path()?;
+        for path in &config.paths {
+            if path.is_dir() {
+                libinput.watch(path)?;
+            } else {
+                libinput.add_device(path)?;
+            }
+        }
+        libinput
+    };
     let mut kbstat = KbStat::new();
+    let mut consumerstat = ConsumerStat::new();
     let mut mousestat = MouseStat::new();
+    let mut digitizerstat = DigitizerStat::new();
+    let mut keyboards = Vec::<Device>::new();
+    let mut leds = Leds::empty();
+    let mut local_leds = None::<Leds>;
 
-    let mut stream_tx = Option::<mpsc::UnboundedSender<InputEvent>>::None;
+    let mut grabbed = false;
+    let escape_timer = Fuse::terminated();
+    tokio::pin!(escape_timer);
     loop {
         select! {
             event = libinput.next().fuse() => {
@@ -88,12 +356,73 @@ async fn input_loop(
                 let event = match event {
                     LibinputEvent::Device(DeviceEvent::Added(evt)) => {
                         let mut device = evt.device();
-                        configure_device(&mut device);
+                        if !rules::is_allowed(config, &device) {
+                            log::info!("ignore device: {} ({})", device.name(), device.sysname());
+                            // libinput closes the device. so it is never grabbed.
+                            let mode = SendEventsMode::DISABLED;
+                            if let Err(e) = device.config_send_events_set_mode(mode) {
+                                log::warn!("failed to disable device {:?}", e);
+                            }
+                        } else {
+                            log::debug!("add device: {} ({})", device.name(), device.sysname());
+                            configure_device(&mut device);
+                            remap.add_device(&device);
+                            if rules::matches_any(&config.no_grab, &device) {
+                                if let Err(e) = libinput.set_device_grab(&device, false) {
+                                    log::warn!("failed to exclude device from grab {:?}", e);
+                                }
+                            }
+                            if device.has_capability(DeviceCapability::Keyboard) {
+                                device.led_update(local_leds.unwrap_or(leds).into());
+                                keyboards.push(device);
+                            }
+                        }
+                        None
+                    }
+                    LibinputEvent::Device(DeviceEvent::Removed(evt)) => {
+                        let device = evt.device();
+                        keyboards.retain(|d| d != &device);
+                        remap.remove_device(&device);
                         None
                     }
                     LibinputEvent::Keyboard(kbd) => {
-                        kbstat.recv(&kbd);
-                        Some(InputEvent::from(kbstat.clone()))
+                        if consumerstat.recv(&kbd) {
+                            Some(InputEvent::from(consumerstat.clone()))
+                        } else if let Some((key, pressed)) = KbStat::key_of(&kbd) {
+                            if let Some(key) = remap.map(&kbd.device(), key, pressed) {
+                                if pressed && !MetaKeys::is_modifier(&key) {
+                                    subscribers.key_pressed();
+                                }
+                                kbstat.recv(key, pressed);
+                                if !config.escape.matches(&kbstat) {
+                                    escape_timer.set(Fuse::terminated());
+                                } else if escape_timer.is_terminated() {
+                                    let hold = Duration::from_secs(config.escape_hold);
+                                    escape_timer.set(tokio::time::sleep(hold).fuse());
+                                }
+                                match host_switch.iter().position(|c| c.matches(&kbstat)) {
+                                    // the host never sees the escape chord.
+                                    _ if config.escape.matches(&kbstat) => None,
+                                    // an empty slot also consumes the chord.
+                                    Some(slot) if !matches!(
+                                        subscribers.switch_host(slot),
+                                        Err(HostError::Captured)
+                                    ) => None,
+                                    _ => match &pairing {
+                                        Some((chord, tx)) if chord.matches(&kbstat) => {
+                                            // the host is not notified.
+                                            tx.unbounded_send(()).ok();
+                                            None
+                                        }
+                                        _ => Some(InputEvent::from(kbstat.clone())),
+                                    },
+                                }
+                            } else {
+                                None
+                            }
+                        } else {
+                            None
+                        }
                     }
                     LibinputEvent::Pointer(PointerEvent::Motion(motion)) => {
                         mousestat.recv_motion(&motion);
@@ -107,14 +436,24 @@ async fn input_loop(
                         mousestat.recv_axis(&axis);
                         Some(InputEvent::from(mousestat.clone()))
                     }
+                    LibinputEvent::Pointer(PointerEvent::MotionAbsolute(motion)) => {
+                        digitizerstat.recv_motion_absolute(&motion);
+                        Some(InputEvent::from(digitizerstat.clone()))
+                    }
+                    LibinputEvent::Touch(touch) => {
+                        digitizerstat
+                            .recv_touch(&touch)
+                            .then(|| InputEvent::from(digitizerstat.clone()))
+                    }
+                    LibinputEvent::Tablet(tool) => {
+                        digitizerstat
+                            .recv_tablet_tool(&tool)
+                            .then(|| InputEvent::from(digitizerstat.clone()))
+                    }
                     _ => None,
                 };
-                if let (Some(event), Some(tx)) = (event, stream_tx.as_mut()) {
-                    if let Err(err) = tx.unbounded_send(event) {
-                        if err.is_disconnected() {
-                            stream_tx = None;
-                        }
-                    }
+                if let Some(event) = event {
+                    subscribers.send(event);
                 }
             }
 
@@ -122,40 +461,180 @@ async fn input_loop(
                 match control {
                     Some(Control::BeginSubscribe(new_subscribe)) => {
                         log::debug!("begin capture input.");
-                        if grab {
-                            libinput.grab()?;
-                        }
-                        stream_tx = Some(new_subscribe);
+                        // release all keys on the host before the input is captured.
+                        subscribers.release_all();
+                        subscribers.exclusive = Some(new_subscribe);
                     }
                     Some(Control::EndSubscribe) => {
                         log::debug!("end capture input.");
-                        if grab {
-                            libinput.ungrab()?;
+                        subscribers.exclusive = None;
+                    }
+                    Some(Control::AddHost(slot, addr, tx)) => {
+                        log::debug!("add host {}.", slot + 1);
+                        subscribers.add_host(slot, tx);
+                        monitor.host_connected(slot, addr);
+                    }
+                    Some(Control::RemoveHost(slot)) => {
+                        log::debug!("remove host {}.", slot + 1);
+                        subscribers.remove_host(slot);
+                        monitor.host_disconnected(slot);
+                    }
+                    Some(Control::SwitchHost(slot, reply)) => {
+                        reply.send(subscribers.switch_host(slot)).ok();
+                    }
+                    Some(Control::SuspendHost(slot, suspended)) => {
+                        log::debug!("host {} suspended: {}.", slot + 1, suspended);
+                        subscribers.suspended[slot] = suspended;
+                    }
+                    Some(Control::Leds(new_leds)) => {
+                        log::debug!("update leds {:?}", new_leds);
+                        leds = new_leds;
+                        if local_leds.is_none() {
+                            for device in &mut keyboards {
+                                device.led_update(leds.into());
+                            }
                         }
-                        stream_tx = None;
+                    }
+                    Some(Control::LocalLeds(new_leds)) => {
+                        local_leds = new_leds;
+                        for device in &mut keyboards {
+                            device.led_update(local_leds.unwrap_or(leds).into());
+                        }
+                    }
+                    Some(Control::ObserveKeys(tx)) => subscribers.observers.push(tx),
+                    Some(Control::Inject(event, reply)) => {
+                        reply.send(subscribers.inject(event)).ok();
                     }
                     None => return Ok(()),
                 }
             }
+
+            _ = escape_timer => {
+                subscribers.toggle_detached();
+            }
+        }
+
+        let wants_grab = !subscribers.detached
+            && match config.grab {
+                GrabPolicy::Never => false,
+                GrabPolicy::WhileConnected => subscribers.wants_grab(),
+                GrabPolicy::Always => true,
+            };
+        if grabbed != wants_grab {
+            grabbed = wants_grab;
+            if grabbed {
+                libinput.grab()?;
+            } else {
+                libinput.ungrab()?;
+            }
+            monitor.set_grabbed(grabbed);
         }
+        monitor.set_active_host(subscribers.active_host());
     }
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct InputSource {
     stream_lock: Arc<Mutex<()>>,
+    type_lock: Arc<Mutex<()>>,
+    host_slots: Arc<StdMutex<Vec<bool>>>,
     control_tx: mpsc::UnboundedSender<Control>,
 }
 
 impl InputSource {
-    pub(crate) fn new(grab: bool) -> io::Result<(Self, impl Future<Output = anyhow::Result<()>>)> {
+    pub(crate) fn new(
+        config: InputConfig,
+        remap: RemapConfig,
+        host_switch: Vec<Chord>,
+        pairing: Option<(Chord, mpsc::UnboundedSender<()>)>,
+        monitor: Monitor,
+    ) -> io::Result<(Self, InputLoop)> {
         let (control_tx, control_rx) = mpsc::unbounded();
 
         let me = Self {
             stream_lock: Arc::new(Mutex::new(())),
+            type_lock: Arc::new(Mutex::new(())),
+            host_slots: Arc::new(StdMutex::new(vec![false; host_switch.len().max(1)])),
             control_tx,
         };
-        Ok((me, input_loop(control_rx, grab)))
+        Ok((
+            me,
+            InputLoop {
+                control_rx,
+                config,
+                remap,
+                subscribers: Subscribers::new(host_switch.len().max(1)),
+                host_switch,
+                pairing,
+                monitor,
+            },
+        ))
+    }
+
+    pub(crate) fn set_leds(&self, leds: Leds) -> anyhow::Result<()> {
+        self.control_tx.unbounded_send(Control::Leds(leds))?;
+        Ok(())
+    }
+
+    /// Override the host LEDs. `None` restores them.
+    pub(crate) fn set_local_leds(&self, leds: Option<Leds>) -> anyhow::Result<()> {
+        self.control_tx.unbounded_send(Control::LocalLeds(leds))?;
+        Ok(())
+    }
+
+    /// Send a report to the active host. Fails if it is not delivered.
+    async fn inject(&self, event: InputEvent) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.control_tx.unbounded_send(Control::Inject(event, tx))?;
+        rx.await??;
+        Ok(())
+    }
+
+    /// Type the text on the active host. `delay` is waited after each report.
+    pub(crate) async fn type_text(
+        &self,
+        text: &str,
+        layout: Layout,
+        delay: Duration,
+    ) -> anyhow::Result<()> {
+        let reports = text::reports(layout, text)?;
+        let _guard = self.type_lock.lock().await;
+        for report in reports {
+            self.inject(report.into()).await?;
+            tokio::time::sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    /// Press and release the chords on the active host.
+    pub(crate) async fn press_chords(
+        &self,
+        chords: &[Chord],
+        delay: Duration,
+    ) -> anyhow::Result<()> {
+        let _guard = self.type_lock.lock().await;
+        for chord in chords {
+            for report in [chord.to_kbstat(), KbStat::new()] {
+                self.inject(report.into()).await?;
+                tokio::time::sleep(delay).await;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn switch_host(&self, slot: usize) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.control_tx
+            .unbounded_send(Control::SwitchHost(slot, tx))?;
+        rx.await?
+            .map_err(|err| anyhow::anyhow!("host {}: {}", slot + 1, err))
+    }
+
+    /// Notified of the local key presses without taking the input from the hosts.
+    pub(crate) fn observe_keys(&self) -> anyhow::Result<mpsc::UnboundedReceiver<()>> {
+        let (tx, rx) = mpsc::unbounded();
+        self.control_tx.unbounded_send(Control::ObserveKeys(tx))?;
+        Ok(rx)
     }
 
     pub(crate) async fn use_stream(&self) -> anyhow::Result<InputStream<'_>> {
@@ -171,3 +650,117 @@ impl InputSource {
         })
     }
 }
+
+impl InputSource {
+    pub(crate) fn use_host_stream(&self, addr: Address) -> anyhow::Result<HostStream> {
+        let slot = {
+            let mut slots = self
+                .host_slots
+                .lock()
+                .map_err(|_| anyhow::anyhow!("failed to lock"))?;
+            let slot = slots
+                .iter()
+                .position(|used| !used)
+                .ok_or_else(|| anyhow::anyhow!("no free host slot."))?;
+            slots[slot] = true;
+            slot
+        };
+
+        let (tx, rx) = mpsc::unbounded();
+        let stream = HostStream {
+            slot,
+            slots: self.host_slots.clone(),
+            rx,
+            control_tx: self.control_tx.clone(),
+        };
+        self.control_tx
+            .unbounded_send(Control::AddHost(slot, addr, tx))?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switch_host() {
+        let mut subscribers = Subscribers::new(2);
+        assert!(matches!(subscribers.switch_host(1), Err(HostError::NoHost)));
+
+        let (tx, _rx) = mpsc::unbounded();
+        subscribers.add_host(1, tx);
+        assert_eq!(subscribers.active, 1);
+        assert!(matches!(subscribers.switch_host(0), Err(HostError::NoHost)));
+        assert!(subscribers.switch_host(1).is_ok());
+        assert!(matches!(subscribers.switch_host(2), Err(HostError::NoHost)));
+
+        let (tx, _rx) = mpsc::unbounded();
+        subscribers.exclusive = Some(tx);
+        assert!(matches!(
+            subscribers.switch_host(1),
+            Err(HostError::Captured)
+        ));
+    }
+
+    #[test]
+    fn test_observe_keys() {
+        let mut subscribers = Subscribers::new(2);
+        let (tx, mut host) = mpsc::unbounded();
+        subscribers.add_host(0, tx);
+        let (tx, _rx) = mpsc::unbounded();
+        subscribers.add_host(1, tx);
+
+        // waiting for a key to restart advertising.
+        let (tx, mut observer) = mpsc::unbounded();
+        subscribers.observers.push(tx);
+        assert!(subscribers.inject(KbStat::new().into()).is_ok());
+        assert!(host.try_next().unwrap().is_some());
+        assert!(subscribers.switch_host(1).is_ok());
+        assert!(subscribers.switch_host(0).is_ok());
+
+        subscribers.key_pressed();
+        assert_eq!(observer.try_next().unwrap(), Some(()));
+
+        drop(observer);
+        subscribers.key_pressed();
+        assert!(subscribers.observers.is_empty());
+    }
+
+    #[test]
+    fn test_inject() {
+        let mut subscribers = Subscribers::new(1);
+        let event = || InputEvent::from(KbStat::new());
+        assert!(matches!(
+            subscribers.inject(event()),
+            Err(HostError::NoHost)
+        ));
+
+        let (tx, mut rx) = mpsc::unbounded();
+        subscribers.add_host(0, tx);
+        assert!(subscribers.inject(event()).is_ok());
+        assert!(rx.try_next().unwrap().is_some());
+
+        subscribers.detached = true;
+        assert!(matches!(
+            subscribers.inject(event()),
+            Err(HostError::Detached)
+        ));
+        subscribers.detached = false;
+
+        let (tx, mut exclusive) = mpsc::unbounded();
+        subscribers.exclusive = Some(tx);
+        assert!(matches!(
+            subscribers.inject(event()),
+            Err(HostError::Captured)
+        ));
+        assert!(exclusive.try_next().is_err());
+        subscribers.exclusive = None;
+
+        drop(rx);
+        assert!(matches!(
+            subscribers.inject(event()),
+            Err(HostError::NoHost)
+        ));
+    }
+}
@@ -0,0 +1,319 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::kbstat::{KbStat, MetaKeys};
+use crate::hid::KeyboardUsageId as K;
+
+/// Keyboard layout configured on the host. Used to type text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    #[default]
+    Us,
+    Jis,
+    De,
+}
+
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "us" => Ok(Self::Us),
+            "jis" | "jp" => Ok(Self::Jis),
+            "de" => Ok(Self::De),
+            _ => anyhow::bail!("unknown layout: {}", s),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("character {0:?} can not be typed with layout {1:?}")]
+pub struct UnsupportedChar(char, Layout);
+
+const NONE: MetaKeys = MetaKeys::empty();
+const SHIFT: MetaKeys = MetaKeys::LEFT_SHIFT;
+const ALTGR: MetaKeys = MetaKeys::RIGHT_ALT;
+
+type Stroke = (MetaKeys, K);
+
+/// Keys same in all of the layouts.
+fn common(c: char) -> Option<Stroke> {
+    const LETTERS: [K; 26] = [
+        K::KEY_A,
+        K::KEY_B,
+        K::KEY_C,
+        K::KEY_D,
+        K::KEY_E,
+        K::KEY_F,
+        K::KEY_G,
+        K::KEY_H,
+        K::KEY_I,
+        K::KEY_J,
+        K::KEY_K,
+        K::KEY_L,
+        K::KEY_M,
+        K::KEY_N,
+        K::KEY_O,
+        K::KEY_P,
+        K::KEY_Q,
+        K::KEY_R,
+        K::KEY_S,
+        K::KEY_T,
+        K::KEY_U,
+        K::KEY_V,
+        K::KEY_W,
+        K::KEY_X,
+        K::KEY_Y,
+        K::KEY_Z,
+    ];
+    const DIGITS: [K; 10] = [
+        K::KEY_0,
+        K::KEY_1,
+        K::KEY_2,
+        K::KEY_3,
+        K::KEY_4,
+        K::KEY_5,
+        K::KEY_6,
+        K::KEY_7,
+        K::KEY_8,
+        K::KEY_9,
+    ];
+
+    Some(match c {
+        'a'..='z' => (NONE, LETTERS[c as usize - 'a' as usize].clone()),
+        'A'..='Z' => (SHIFT, LETTERS[c as usize - 'A' as usize].clone()),
+        '0'..='9' => (NONE, DIGITS[c as usize - '0' as usize].clone()),
+        ' ' => (NONE, K::KEY_SPACE),
+        '\n' => (NONE, K::KEY_ENTER),
+        '\t' => (NONE, K::KEY_TAB),
+        _ => return None,
+    })
+}
+
+fn us(c: char) -> Option<Stroke> {
+    Some(match c {
+        '!' => (SHIFT, K::KEY_1),
+        '@' => (SHIFT, K::KEY_2),
+        '#' => (SHIFT, K::KEY_3),
+        '$' => (SHIFT, K::KEY_4),
+        '%' => (SHIFT, K::KEY_5),
+        '^' => (SHIFT, K::KEY_6),
+        '&' => (SHIFT, K::KEY_7),
+        '*' => (SHIFT, K::KEY_8),
+        '(' => (SHIFT, K::KEY_9),
+        ')' => (SHIFT, K::KEY_0),
+        '-' => (NONE, K::KEY_MINUS),
+        '_' => (SHIFT, K::KEY_MINUS),
+        '=' => (NONE, K::KEY_EQUAL),
+        '+' => (SHIFT, K::KEY_EQUAL),
+        '[' => (NONE, K::KEY_LEFTBRACE),
+        '{' => (SHIFT, K::KEY_LEFTBRACE),
+        ']' => (NONE, K::KEY_RIGHTBRACE),
+        '}' => (SHIFT, K::KEY_RIGHTBRACE),
+        '\\' => (NONE, K::KEY_BACKSLASH),
+        '|' => (SHIFT, K::KEY_BACKSLASH),
+        ';' => (NONE, K::KEY_SEMICOLON),
+        ':' => (SHIFT, K::KEY_SEMICOLON),
+        '\'' => (NONE, K::KEY_APOSTROPHE),
+        '"' => (SHIFT, K::KEY_APOSTROPHE),
+        '`' => (NONE, K::KEY_GRAVE),
+        '~' => (SHIFT, K::KEY_GRAVE),
+        ',' => (NONE, K::KEY_COMMA),
+        '<' => (SHIFT, K::KEY_COMMA),
+        '.' => (NONE, K::KEY_DOT),
+        '>' => (SHIFT, K::KEY_DOT),
+        '/' => (NONE, K::KEY_SLASH),
+        '?' => (SHIFT, K::KEY_SLASH),
+        _ => return common(c),
+    })
+}
+
+fn jis(c: char) -> Option<Stroke> {
+    Some(match c {
+        '!' => (SHIFT, K::KEY_1),
+        '"' => (SHIFT, K::KEY_2),
+        '#' => (SHIFT, K::KEY_3),
+        '$' => (SHIFT, K::KEY_4),
+        '%' => (SHIFT, K::KEY_5),
+        '&' => (SHIFT, K::KEY_6),
+        '\'' => (SHIFT, K::KEY_7),
+        '(' => (SHIFT, K::KEY_8),
+        ')' => (SHIFT, K::KEY_9),
+        '-' => (NONE, K::KEY_MINUS),
+        '=' => (SHIFT, K::KEY_MINUS),
+        '^' => (NONE, K::KEY_EQUAL),
+        '~' => (SHIFT, K::KEY_EQUAL),
+        '¥' => (NONE, K::KEY_YEN),
+        '|' => (SHIFT, K::KEY_YEN),
+        '@' => (NONE, K::KEY_LEFTBRACE),
+        '`' => (SHIFT, K::KEY_LEFTBRACE),
+        '[' => (NONE, K::KEY_RIGHTBRACE),
+        '{' => (SHIFT, K::KEY_RIGHTBRACE),
+        ';' => (NONE, K::KEY_SEMICOLON),
+        '+' => (SHIFT, K::KEY_SEMICOLON),
+        ':' => (NONE, K::KEY_APOSTROPHE),
+        '*' => (SHIFT, K::KEY_APOSTROPHE),
+        ']' => (NONE, K::KEY_NON_US_SHARP),
+        '}' => (SHIFT, K::KEY_NON_US_SHARP),
+        ',' => (NONE, K::KEY_COMMA),
+        '<' => (SHIFT, K::KEY_COMMA),
+        '.' => (NONE, K::KEY_DOT),
+        '>' => (SHIFT, K::KEY_DOT),
+        '/' => (NONE, K::KEY_SLASH),
+        '?' => (SHIFT, K::KEY_SLASH),
+        '\\' => (NONE, K::KEY_RO),
+        '_' => (SHIFT, K::KEY_RO),
+        _ => return common(c),
+    })
+}
+
+fn de(c: char) -> Option<Stroke> {
+    Some(match c {
+        'y' => (NONE, K::KEY_Z),
+        'Y' => (SHIFT, K::KEY_Z),
+        'z' => (NONE, K::KEY_Y),
+        'Z' => (SHIFT, K::KEY_Y),
+        '!' => (SHIFT, K::KEY_1),
+        '"' => (SHIFT, K::KEY_2),
+        '§' => (SHIFT, K::KEY_3),
+        '$' => (SHIFT, K::KEY_4),
+        '%' => (SHIFT, K::KEY_5),
+        '&' => (SHIFT, K::KEY_6),
+        '/' => (SHIFT, K::KEY_7),
+        '(' => (SHIFT, K::KEY_8),
+        ')' => (SHIFT, K::KEY_9),
+        '=' => (SHIFT, K::KEY_0),
+        '²' => (ALTGR, K::KEY_2),
+        '³' => (ALTGR, K::KEY_3),
+        '{' => (ALTGR, K::KEY_7),
+        '[' => (ALTGR, K::KEY_8),
+        ']' => (ALTGR, K::KEY_9),
+        '}' => (ALTGR, K::KEY_0),
+        'ß' => (NONE, K::KEY_MINUS),
+        '?' => (SHIFT, K::KEY_MINUS),
+        '\\' => (ALTGR, K::KEY_MINUS),
+        '@' => (ALTGR, K::KEY_Q),
+        '€' => (ALTGR, K::KEY_E),
+        'µ' => (ALTGR, K::KEY_M),
+        'ü' => (NONE, K::KEY_LEFTBRACE),
+        'Ü' => (SHIFT, K::KEY_LEFTBRACE),
+        '+' => (NONE, K::KEY_RIGHTBRACE),
+        '*' => (SHIFT, K::KEY_RIGHTBRACE),
+        '~' => (ALTGR, K::KEY_RIGHTBRACE),
+        'ö' => (NONE, K::KEY_SEMICOLON),
+        'Ö' => (SHIFT, K::KEY_SEMICOLON),
+        'ä' => (NONE, K::KEY_APOSTROPHE),
+        'Ä' => (SHIFT, K::KEY_APOSTROPHE),
+        '#' => (NONE, K::KEY_NON_US_SHARP),
+        '\'' => (SHIFT, K::KEY_NON_US_SHARP),
+        '°' => (SHIFT, K::KEY_GRAVE),
+        '<' => (NONE, K::KEY_102ND),
+        '>' => (SHIFT, K::KEY_102ND),
+        '|' => (ALTGR, K::KEY_102ND),
+        ',' => (NONE, K::KEY_COMMA),
+        ';' => (SHIFT, K::KEY_COMMA),
+        '.' => (NONE, K::KEY_DOT),
+        ':' => (SHIFT, K::KEY_DOT),
+        '-' => (NONE, K::KEY_SLASH),
+        '_' => (SHIFT, K::KEY_SLASH),
+        _ => return common(c),
+    })
+}
+
+/// Dead keys. Followed by a space to type the character itself.
+fn de_dead(c: char) -> Option<Stroke> {
+    Some(match c {
+        '^' => (NONE, K::KEY_GRAVE),
+        '´' => (NONE, K::KEY_EQUAL),
+        '`' => (SHIFT, K::KEY_EQUAL),
+        _ => return None,
+    })
+}
+
+fn strokes(layout: Layout, c: char) -> Option<Vec<Stroke>> {
+    match layout {
+        Layout::Us => us(c).map(|s| vec![s]),
+        Layout::Jis => jis(c).map(|s| vec![s]),
+        Layout::De => de(c)
+            .map(|s| vec![s])
+            .or_else(|| de_dead(c).map(|s| vec![s, (NONE, K::KEY_SPACE)])),
+    }
+}
+
+/// Press and release reports to type the text.
+pub(crate) fn reports(layout: Layout, text: &str) -> Result<Vec<KbStat>, UnsupportedChar> {
+    let mut result = vec![];
+    for c in text.chars() {
+        // CRLF is typed as a single Enter.
+        if c == '\r' {
+            continue;
+        }
+        let strokes = strokes(layout, c).ok_or(UnsupportedChar(c, layout))?;
+        for (meta, key) in strokes {
+            result.push(KbStat::from_key(meta, key));
+            result.push(KbStat::new());
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strokes_of(layout: Layout, text: &str) -> Vec<(MetaKeys, Vec<K>)> {
+        reports(layout, text)
+            .unwrap()
+            .iter()
+            .map(|r| (r.meta(), r.keys().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_reports() {
+        assert_eq!(
+            strokes_of(Layout::Us, "aB"),
+            vec![
+                (NONE, vec![K::KEY_A]),
+                (NONE, vec![]),
+                (SHIFT, vec![K::KEY_B]),
+                (NONE, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_crlf() {
+        assert_eq!(strokes_of(Layout::Us, "\r\n"), strokes_of(Layout::Us, "\n"));
+    }
+
+    #[test]
+    fn test_reports_layout() {
+        assert_eq!(strokes_of(Layout::Us, "z")[0], (NONE, vec![K::KEY_Z]));
+        assert_eq!(strokes_of(Layout::De, "z")[0], (NONE, vec![K::KEY_Y]));
+        assert_eq!(
+            strokes_of(Layout::Jis, "@")[0],
+            (NONE, vec![K::KEY_LEFTBRACE])
+        );
+    }
+
+    #[test]
+    fn test_reports_dead_key() {
+        assert_eq!(
+            strokes_of(Layout::De, "^"),
+            vec![
+                (NONE, vec![K::KEY_GRAVE]),
+                (NONE, vec![]),
+                (NONE, vec![K::KEY_SPACE]),
+                (NONE, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_unsupported() {
+        assert!(reports(Layout::Us, "aé").is_err());
+    }
+}
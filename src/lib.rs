@@ -1,28 +1,51 @@
 #![warn(clippy::all)]
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use bdaddr::{Address, RandomDeviceAddress};
 use btknmle_keydb::Store;
 use btmgmt::client::Client as MgmtClient;
 use btmgmt::packet::event::Event as MgmtEvent;
-use btmgmt::packet::{command as cmd, ControllerIndex};
+use btmgmt::packet::{command as cmd, ConfirmHint, ControllerIndex};
 use futures_channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use futures_channel::oneshot::{self, Sender};
-use futures_util::future::{abortable, AbortHandle};
+use futures_util::future::{abortable, AbortHandle, Either, LocalBoxFuture};
 use futures_util::lock::Mutex;
+use futures_util::stream::FuturesUnordered;
 use futures_util::{pin_mut, select, FutureExt, StreamExt, TryFutureExt};
+use gatt::server::{Connection, Event as GattEvent};
 use gatt::Server;
+use tokio::io::AsyncWriteExt as _;
+use tokio::sync::watch;
 
+use crate::config::{AdvertisingPolicy, Config, Hid};
+use crate::input::kbstat::{KbStat, Leds};
 use crate::input::InputEvent;
+use crate::lockout::Lockout;
+use crate::monitor::Monitor;
+use crate::supervisor::supervise;
 
+pub use crate::controller::Controller;
+pub use crate::input::{Chord, Layout};
+
+mod battery;
+pub mod bonds;
+pub mod config;
+pub mod control;
+mod controller;
 mod gap;
 mod hid;
 mod hogp;
 mod input;
+mod lockout;
+mod monitor;
+mod passkey;
 mod sig;
+mod supervisor;
 
 fn authenticated(ltk: &btmgmt::packet::LongTermKey, addr: &Address) -> bool {
     match ltk.key_type() {
@@ -52,19 +75,34 @@ fn resolve_identity_address(store: &Store, addr: &Address) -> Option<Address> {
     }
 }
 
+async fn disconnect(gap: &MgmtClient, device_id: ControllerIndex, addr: Address) {
+    if let Err(err) = gap.call(device_id, cmd::Disconnect::new(addr)).await {
+        log::warn!("failed to disconnect: {}", err);
+    }
+}
+
 async fn store_keys(
     device_id: ControllerIndex,
     gap: &MgmtClient,
-    mut store: Store,
-    mut auth_channel: UnboundedReceiver<(Address, Sender<()>)>,
+    store: &mut Store,
+    auth_channel: &mut UnboundedReceiver<(Address, Sender<()>)>,
+    bond_timeout: Duration,
+    monitor: Monitor,
 ) -> anyhow::Result<()> {
+    monitor.set_bonds(bonds::addresses(store));
     let events = gap.events().await;
     let mut events = events
         .filter_map(|(idx, evt)| future::ready((idx == device_id).then(|| evt)))
         .fuse();
-    let mut pendings = HashMap::<Address, Sender<()>>::new();
+    // keyed by the generation too. a deadline of the previous connection must not fire.
+    let mut pendings = HashMap::<Address, (u64, Sender<()>)>::new();
+    let mut generation = 0u64;
+    let mut deadlines = FuturesUnordered::new();
 
     loop {
+        // the connection is closed.
+        pendings.retain(|_, (_, sender)| !sender.is_canceled());
+
         select! {
             item = events.next() => {
                 let event = if let Some(event) = item {
@@ -77,14 +115,15 @@ async fn store_keys(
                     MgmtEvent::NewLongTermKey(evt) => {
                         if *evt.store_hint() {
                             store.add_ltk(evt.key().clone()).await?;
+                            monitor.set_bonds(bonds::addresses(store));
 
                             let addr = evt.key().address();
-                            if let Some(sender) = pendings.remove(&addr) {
-                                if bonded(&store, &addr) {
+                            if let Some((gen, sender)) = pendings.remove(&addr) {
+                                if bonded(store, &addr) {
                                     log::debug!("New bonded for {}", evt.key().address());
                                     sender.send(()).ok();
                                 } else {
-                                    pendings.insert(addr, sender);
+                                    pendings.insert(addr, (gen, sender));
                                 }
                             }
                         }
@@ -93,23 +132,33 @@ async fn store_keys(
                     MgmtEvent::NewIdentityResolvingKey(evt) => {
                         if *evt.store_hint() {
                             store.add_irk(evt.key().clone()).await?;
-                            if let Some(sender) = pendings.remove(&evt.address()) {
+                            monitor.set_bonds(bonds::addresses(store));
+                            if let Some((gen, sender)) = pendings.remove(&evt.address()) {
                                 let addr = evt.address();
-                                let addr = if let Some(newaddr) = resolve_identity_address(&store, &addr) {
+                                let resolved = resolve_identity_address(store, &addr);
+                                let addr = if let Some(newaddr) = resolved {
                                     log::debug!("resolved {:?} -> {:?}", addr, newaddr);
                                     newaddr
                                 } else {
                                     addr
                                 };
-                                if bonded(&store, &addr) {
+                                if bonded(store, &addr) {
                                     sender.send(()).ok();
                                 } else {
-                                    pendings.insert(addr, sender);
+                                    pendings.insert(addr, (gen, sender));
                                 }
                             }
                         }
                     }
 
+                    MgmtEvent::DeviceUnpaired(evt) => {
+                        let addr = evt.address();
+                        if store.remove(&addr).await? {
+                            log::info!("unpaired {}", addr);
+                            monitor.set_bonds(bonds::addresses(store));
+                        }
+                    }
+
                     _ => {}
                 }
             },
@@ -121,88 +170,218 @@ async fn store_keys(
                     return Ok(());
                 };
 
-                let addr = if let Some(resolved) = resolve_identity_address(&store, &addr) {
+                let addr = if let Some(resolved) = resolve_identity_address(store, &addr) {
                     log::debug!("resolved {:?} -> {:?}", addr, resolved);
                     resolved
                 } else {
                     addr
                 };
-                if bonded(&store, &addr) {
+                if bonded(store, &addr) {
                     sender.send(()).ok();
                 } else {
                     log::debug!("Pending for {}", addr);
-                    pendings.insert(addr, sender);
+                    generation += 1;
+                    deadlines.push(tokio::time::sleep(bond_timeout).map({
+                        let key = (addr.clone(), generation);
+                        move |_| key
+                    }));
+                    pendings.insert(addr, (generation, sender));
+                }
+            },
+
+            (addr, gen) = deadlines.select_next_some() => {
+                if matches!(pendings.get(&addr), Some((g, _)) if *g == gen) {
+                    pendings.remove(&addr);
+                    log::info!("disconnect {}: not bonded in {:?}.", addr, bond_timeout);
+                    disconnect(gap, device_id.clone(), addr).await;
                 }
             },
         }
     }
 }
 
-fn add_passkey(buf: &mut u32, kbstat: &input::kbstat::KbStat) -> bool {
-    use hid::KeyboardUsageId::*;
-
-    if let Some(key) = kbstat.keys().iter().next() {
-        let k = match key {
-            KEY_0 => 0,
-            KEY_1 => 1,
-            KEY_2 => 2,
-            KEY_3 => 3,
-            KEY_4 => 4,
-            KEY_5 => 5,
-            KEY_6 => 6,
-            KEY_7 => 7,
-            KEY_8 => 8,
-            KEY_9 => 9,
-            KEY_ENTER => return true,
-            _ => return false,
-        };
-        *buf = buf.wrapping_mul(10) + k;
-    }
-    false
+/// Answer to the host by the local input.
+#[derive(Debug)]
+enum Reply {
+    Passkey(Option<u32>),
+    Confirm(bool),
 }
 
-async fn fill_passkey(input: &mut input::InputStream<'_>) -> anyhow::Result<u32> {
-    let mut passkey = 0;
-    while let Some(input_event) = input.next().await {
-        let kbstat = if let input::InputEvent::Keyboard(kbstat) = input_event {
-            kbstat
-        } else {
-            continue;
-        };
-        if add_passkey(&mut passkey, &kbstat) {
-            return Ok(passkey);
-        }
+type PendingReply<'a> = (Address, LocalBoxFuture<'a, anyhow::Result<Reply>>);
+
+/// Pending forever if nothing is waited.
+async fn local_reply(pending: &mut Option<PendingReply<'_>>) -> (Address, anyhow::Result<Reply>) {
+    let reply = match pending {
+        Some((addr, reply)) => (addr.clone(), reply.await),
+        None => future::pending().await,
+    };
+    *pending = None;
+    reply
+}
+
+/// The host gave up. (e.g. cancelled or disconnected)
+fn abort_reply(pending: &mut Option<PendingReply<'_>>, addr: &Address) {
+    if matches!(pending, Some((a, _)) if a == addr) {
+        log::info!("{} gave up pairing.", addr);
+        *pending = None;
     }
-    anyhow::bail!("failed to fill passkey.")
 }
 
 async fn passkey_input(
     device_id: ControllerIndex,
     gap: &MgmtClient,
     input: input::InputSource,
+    config: &config::Pairing,
+    monitor: Monitor,
 ) -> anyhow::Result<()> {
     let events = gap.events().await;
     let mut events = events.filter_map(|(idx, evt)| future::ready((idx == device_id).then(|| evt)));
+    let timeout = Duration::from_secs(config.passkey_timeout);
+    let mut pending = None::<PendingReply<'_>>;
 
-    while let Some(event) = events.next().await {
-        if let MgmtEvent::UserPasskeyRequest(event) = event {
-            log::trace!("begin passkey input.");
-            let mut input = input.use_stream().await?;
-            let passkey = fill_passkey(&mut input).await?;
-            let msg = cmd::UserPasskeyReply::new(event.address().clone(), passkey);
-            gap.call(device_id.clone(), msg).await?;
+    loop {
+        let next = select! {
+            event = events.next().fuse() => Either::Left(event),
+            reply = local_reply(&mut pending).fuse() => Either::Right(reply),
+        };
+        let event = match next {
+            Either::Left(Some(event)) => event,
+            Either::Left(None) => break,
+            Either::Right((addr, reply)) => {
+                match reply? {
+                    Reply::Passkey(Some(passkey)) => {
+                        gap.call(device_id.clone(), cmd::UserPasskeyReply::new(addr, passkey))
+                            .await?;
+                    }
+                    Reply::Passkey(None) => {
+                        gap.call(device_id.clone(), cmd::UserPasskeyNegativeReply::new(addr))
+                            .await?;
+                    }
+                    Reply::Confirm(true) => {
+                        gap.call(device_id.clone(), cmd::UserConfirmationReply::new(addr))
+                            .await?;
+                    }
+                    Reply::Confirm(false) => {
+                        gap.call(
+                            device_id.clone(),
+                            cmd::UserConfirmationNegativeReply::new(addr),
+                        )
+                        .await?;
+                    }
+                }
+                monitor.set_passkey(None);
+                continue;
+            }
+        };
+
+        match event {
+            MgmtEvent::UserPasskeyRequest(event) => {
+                log::info!("Input the passkey shown on {}.", event.address());
+                let reply = passkey::input_passkey(&input, timeout).map_ok(Reply::Passkey);
+                if let Some((addr, _)) = pending.replace((event.address(), reply.boxed_local())) {
+                    log::info!("passkey input for {} aborted.", addr);
+                }
+            }
+            MgmtEvent::UserConfirmationRequest(event) => {
+                let addr = event.address();
+                match event.confirm_hint() {
+                    ConfirmHint::Full => {
+                        let value = u32::from_le_bytes(*event.value());
+                        log::info!("Does {:06} match on {}? [y/n]", value, addr);
+                        monitor.set_passkey(Some(monitor::Passkey::new(&addr, value, true)));
+                    }
+                    // nothing to compare.
+                    ConfirmHint::Simple => log::info!("Pair with {}? [y/n]", addr),
+                }
+                let reply = passkey::confirm(&input, timeout).map_ok(Reply::Confirm);
+                if let Some((addr, _)) = pending.replace((addr, reply.boxed_local())) {
+                    log::info!("confirmation for {} aborted.", addr);
+                }
+            }
+            MgmtEvent::PasskeyNotify(event) => {
+                let addr = event.address();
+                if *event.entered() == 0 {
+                    log::info!("Type {:06} on {}.", event.passkey(), addr);
+                }
+                monitor.set_passkey(Some(monitor::Passkey::new(&addr, *event.passkey(), false)));
+            }
+            // pairing finished.
+            MgmtEvent::NewLongTermKey(..) => monitor.set_passkey(None),
+            MgmtEvent::AuthenticationFailed(event) => {
+                monitor.clear_passkey_of(&event.address());
+                abort_reply(&mut pending, &event.address());
+            }
+            MgmtEvent::DeviceDisconnect(event) => {
+                monitor.clear_passkey_of(&event.address());
+                abort_reply(&mut pending, &event.address());
+            }
+            _ => {}
         }
     }
 
     Ok(())
 }
 
+/// Wait a key press except the modifiers.
+/// The key still reaches the active host.
+async fn wait_key(input: &input::InputSource) -> anyhow::Result<()> {
+    input.observe_keys()?.next().await;
+    Ok(())
+}
+
+/// Start advertising for the policy. `pairing` makes it discoverable for `timeout` seconds.
+async fn start_advertising(
+    gap: &MgmtClient,
+    device_id: ControllerIndex,
+    config: &config::Advertising,
+    pairing: bool,
+) -> anyhow::Result<()> {
+    let (discoverable, timeout) = match config.policy {
+        _ if pairing => (true, config.timeout),
+        AdvertisingPolicy::Timed => (true, config.timeout),
+        AdvertisingPolicy::Always => (true, 0),
+        AdvertisingPolicy::Whitelist | AdvertisingPolicy::Directed => (false, 0),
+    };
+    crate::gap::start_advertising(gap, device_id, discoverable, timeout).await
+}
+
+/// Pending forever if `None`.
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => future::pending().await,
+    }
+}
+
+async fn end_pairing(gap: &MgmtClient, device_id: ControllerIndex) -> anyhow::Result<()> {
+    log::info!("pairing mode finished.");
+    crate::gap::set_bondable(gap, device_id, false).await
+}
+
+/// State of [`advertising`] kept across the restarts.
+#[derive(Debug)]
+struct AdvertisingState {
+    pairing_rx: UnboundedReceiver<()>,
+    last_host: Option<Address>,
+    lockout: Lockout,
+}
+
 async fn advertising(
     device_id: ControllerIndex,
     gap: &MgmtClient,
     input: input::InputSource,
+    config: &Config,
+    max_hosts: usize,
+    state: &mut AdvertisingState,
+    monitor: Monitor,
 ) -> anyhow::Result<()> {
-    let timeout = 60;
+    let pairing_config = &config.pairing;
+    let config = &config.advertising;
+    // only the known hosts can connect except in pairing mode.
+    let restricted = matches!(
+        config.policy,
+        AdvertisingPolicy::Whitelist | AdvertisingPolicy::Directed
+    );
 
     let events = gap.events().await;
     let devid = device_id.clone();
@@ -210,38 +389,141 @@ async fn advertising(
 
     let (mut wakeup_tx, mut wakeup_rx) = mpsc::channel::<()>(1);
     let cancel_handle = Arc::new(Mutex::<Option<AbortHandle>>::new(None));
-    let mut connected = false;
+    // deadline of the pairing mode until a new host connects.
+    let pairing = Cell::new(None::<tokio::time::Instant>);
+    // the new host connected in the pairing mode.
+    let mut pairing_host = None::<Address>;
+    let AdvertisingState {
+        pairing_rx,
+        last_host,
+        lockout,
+    } = state;
+    // connected before (re)started.
+    let mut connected = gap
+        .call(device_id.clone(), cmd::GetConnections)
+        .await?
+        .into_iter()
+        .collect::<Vec<_>>();
+    let full = connected.len() >= max_hosts;
     let mut advertised = crate::gap::is_advertising_enabled(gap, device_id.clone()).await?;
+    monitor.set_advertising(advertised);
+    if restricted {
+        crate::gap::set_bondable(gap, device_id.clone(), false).await?;
+    }
 
     let devid = device_id.clone();
     let connection_watch = async {
         let cancel_handle = cancel_handle.clone();
-        while let Some(event) = events.next().await {
+        loop {
+            let event = select! {
+                event = events.next().fuse() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = sleep_until(pairing.get()).fuse() => {
+                    pairing.set(None);
+                    end_pairing(gap, devid.clone()).await?;
+                    continue;
+                }
+            };
             match event {
-                MgmtEvent::DeviceConnected(..) => {
-                    connected = true;
-                    if let Some(h) = cancel_handle.lock().await.take() {
-                        h.abort();
+                MgmtEvent::DeviceConnected(evt) => {
+                    let addr = evt.address();
+                    if lockout.is_locked(&addr, Instant::now()) {
+                        log::info!("reject {}: locked out by the failed pairings.", addr);
+                        disconnect(gap, devid.clone(), addr).await;
+                        continue;
+                    }
+                    let bonded = monitor.bonds().contains(&addr);
+                    if !bonded && lockout.is_pairing_locked(Instant::now()) {
+                        log::info!(
+                            "reject {}: pairing locked out by the failed pairings.",
+                            addr
+                        );
+                        disconnect(gap, devid.clone(), addr).await;
+                        continue;
+                    }
+                    let allowed = match config.policy {
+                        _ if !restricted || pairing.get().is_some() => true,
+                        AdvertisingPolicy::Directed if last_host.is_some() => {
+                            last_host.as_ref() == Some(&addr)
+                        }
+                        _ => bonded,
+                    };
+                    if !allowed {
+                        log::info!("reject {}: not allowed by the advertising policy.", addr);
+                        disconnect(gap, devid.clone(), addr).await;
+                        continue;
+                    }
+
+                    if bonded {
+                        *last_host = Some(addr.clone());
+                    } else if pairing.take().is_some() {
+                        // keep bondable until this host finishes pairing.
+                        pairing_host = Some(addr.clone());
+                    }
+                    connected.push(addr);
+                    if connected.len() >= max_hosts {
+                        if let Some(h) = cancel_handle.lock().await.take() {
+                            h.abort();
+                        }
+                        crate::gap::stop_advertising(gap, devid.clone()).await?;
                     }
-                    crate::gap::stop_advertising(gap, devid.clone()).await?;
                 }
-                MgmtEvent::DeviceDisconnect(..) => {
-                    connected = false;
+                MgmtEvent::DeviceDisconnect(evt) => {
+                    let addr = evt.address();
+                    if pairing_host.as_ref() == Some(&addr) {
+                        pairing_host = None;
+                        end_pairing(gap, devid.clone()).await?;
+                    }
+                    if let Some(pos) = connected.iter().position(|a| a == &addr) {
+                        connected.remove(pos);
+                    } else {
+                        // rejected.
+                        continue;
+                    }
                     if let Some(h) = cancel_handle.lock().await.take() {
                         h.abort();
                     }
-                    crate::gap::start_advertising(gap, devid.clone(), timeout).await?;
+                    start_advertising(gap, devid.clone(), config, false).await?;
+                }
+                MgmtEvent::NewLongTermKey(evt) if *evt.store_hint() => {
+                    lockout.succeeded(&evt.key().address());
+                    *last_host = Some(evt.key().address());
+                    // only the pairing host can bond in the pairing mode.
+                    if pairing_host.take().is_some() {
+                        end_pairing(gap, devid.clone()).await?;
+                    }
+                }
+                MgmtEvent::AuthenticationFailed(evt) => {
+                    let addr = evt.address();
+                    log::warn!("authentication failed {}: {:?}", addr, evt.status());
+                    if pairing_host.as_ref() == Some(&addr) {
+                        pairing_host = None;
+                        end_pairing(gap, devid.clone()).await?;
+                    }
+                    if lockout.failed(&addr, Instant::now()) {
+                        log::warn!("lock out {} for {} seconds.", addr, pairing_config.lockout);
+                    }
+                    // advertising restarts on disconnected.
+                    disconnect(gap, devid.clone(), addr).await;
                 }
                 MgmtEvent::AdvertisingAdded(..) => {
                     advertised = true;
+                    monitor.set_advertising(advertised);
                     if let Some(h) = cancel_handle.lock().await.take() {
                         h.abort();
                     }
                 }
                 MgmtEvent::AdvertisingRemoved(..) => {
                     advertised = false;
-                    if !connected {
-                        wakeup_tx.try_send(()).ok();
+                    monitor.set_advertising(advertised);
+                    if connected.len() < max_hosts {
+                        if config.policy == AdvertisingPolicy::Timed {
+                            wakeup_tx.try_send(()).ok();
+                        } else {
+                            start_advertising(gap, devid.clone(), config, false).await?;
+                        }
                     }
                 }
                 _ => {}
@@ -252,150 +534,495 @@ async fn advertising(
     .fuse();
 
     let devid = device_id.clone();
-    let input_loop = async {
-        log::info!("Start advertising.");
-        if let Err(err) = crate::gap::start_advertising(gap, device_id, timeout).await {
-            return Err(err);
+    let trigger_loop = async {
+        if full {
+            log::info!("{} hosts connected.", max_hosts);
+        } else {
+            log::info!("Start advertising.");
+            if let Err(err) = start_advertising(gap, devid.clone(), config, false).await {
+                return Err(err);
+            }
         }
 
         loop {
-            wakeup_rx.next().await;
-
-            let device_id = devid.clone();
-            let fut = async {
-                let mut input = input.use_stream().await?;
-                'input: while let Some(event) = input.next().await {
-                    if let input::InputEvent::Keyboard(kbstat) = event {
-                        for key in kbstat.keys() {
-                            use hid::KeyboardUsageId::*;
-
-                            match key {
-                                KEY_LEFT_CTRL | KEY_LEFT_SHIFT | KEY_LEFT_ALT | KEY_LEFT_GUI
-                                | KEY_RIGHT_CTRL | KEY_RIGHT_SHIFT | KEY_RIGHT_ALT
-                                | KEY_RIGHT_GUI => {}
-                                _ => break 'input,
-                            }
-                        }
+            select! {
+                _ = pairing_rx.select_next_some() => {},
+                _ = wakeup_rx.select_next_some() => {
+                    if config.pairing_chord.is_some() {
+                        // only the chord restarts.
+                        continue;
                     }
-                }
-                if let Some(..) = input.next().await {
-                    // FIXME filter Keyboard key without meta key.
-                    log::info!("Start advertising.");
-                    crate::gap::start_advertising(gap, device_id, timeout).await?;
-                }
-                anyhow::Result::<()>::Ok(())
-            };
-            let (fut, handle) = abortable(fut);
-            let mut cancel_handle = cancel_handle.lock().await;
-            *cancel_handle = Some(handle);
-            if let Ok(Err(err)) = fut.await {
-                return Err(err);
+
+                    log::info!("Press any key to start advertising.");
+                    let (fut, handle) = abortable(wait_key(&input));
+                    *cancel_handle.lock().await = Some(handle);
+                    select! {
+                        result = fut.fuse() => match result {
+                            Ok(result) => result?,
+                            // started by the others.
+                            Err(..) => continue,
+                        },
+                        _ = pairing_rx.select_next_some() => {},
+                    }
+                },
+            }
+
+            if restricted {
+                log::info!("Start pairing mode for {} seconds.", config.timeout);
+                let timeout = Duration::from_secs(config.timeout.into());
+                pairing.set(Some(tokio::time::Instant::now() + timeout));
+                crate::gap::set_bondable(gap, devid.clone(), true).await?;
+            } else {
+                log::info!("Start advertising.");
             }
+            start_advertising(gap, devid.clone(), config, true).await?;
         }
     }
     .fuse();
 
     select! {
         r = Box::pin(connection_watch) => r?,
-        r = Box::pin(input_loop) => r?,
+        r = Box::pin(trigger_loop) => r?,
     }
     Ok(())
 }
 
-async fn gatt_loop(
+async fn serve(
+    mut connection: Connection<hogp::Token>,
+    hid: &Hid,
+    battery_level: u8,
+    mut battery: watch::Receiver<u8>,
+    nkro: watch::Receiver<bool>,
     input: input::InputSource,
     auth_channel: UnboundedSender<(Address, Sender<()>)>,
 ) -> anyhow::Result<()> {
-    let mut server = Server::bind()?;
-    server.needs_bond_mitm()?;
+    let addr = connection.address().clone();
+    log::debug!("connected: {:?}", addr);
+    let authenticator = connection.authenticator();
 
-    log::info!("Start serving.");
+    let mut kbdnotify = connection.notification(&hogp::Token::Keyboard)?;
+    let mut mousenotify = connection.notification(&hogp::Token::Mouse)?;
+    let mut bootkbdnotify = connection.notification(&hogp::Token::BootKeyboard)?;
+    let mut bootmousenotify = connection.notification(&hogp::Token::BootMouse)?;
+    let mut nkronotify = if hid.nkro {
+        Some(connection.notification(&hogp::Token::KeyboardNkro)?)
+    } else {
+        None
+    };
+    let mut consumernotify = connection.notification(&hogp::Token::Consumer)?;
+    let mut digitizernotify = if hid.digitizer {
+        Some(connection.notification(&hogp::Token::Digitizer)?)
+    } else {
+        None
+    };
+    let mut batterynotify = connection.notification(&hogp::Token::Battery)?;
+    let mut events = connection.events();
 
-    while let Some(connection) = server.accept(hogp::new()).await? {
-        let addr = connection.address().clone();
-        log::debug!("connected: {:?}", addr);
-        let authenticator = connection.authenticator();
+    let task = connection.run().fuse();
+    pin_mut!(task);
 
-        let mut kbdnotify = connection.notification(&hogp::Token::Keyboard)?;
-        let mut mousenotify = connection.notification(&hogp::Token::Mouse)?;
+    let resolution_multiplier = Cell::new(0);
+    let boot_protocol = Cell::new(false);
+    let (suspend_tx, mut suspend_rx) = mpsc::unbounded::<bool>();
 
-        let task = connection.run().fuse();
-        pin_mut!(task);
+    let ledinput = input.clone();
+    let evtask = async {
+        while let Some(event) = events.next().await {
+            match event {
+                GattEvent::Write(
+                    hogp::Token::KeyboardLed | hogp::Token::BootKeyboardLed,
+                    value,
+                ) => {
+                    if let Some(leds) = value.first() {
+                        ledinput.set_leds(Leds::from_bits_truncate(*leds))?;
+                    }
+                }
+                GattEvent::Write(hogp::Token::ProtocolMode, value) => {
+                    if let Some(mode) = value.first() {
+                        // 0x00: Boot Protocol Mode, 0x01: Report Protocol Mode
+                        log::debug!("protocol mode {}: {}", addr, mode);
+                        boot_protocol.set(*mode == 0x00);
+                    }
+                }
+                GattEvent::Write(hogp::Token::ControlPoint, value) => match value.first() {
+                    Some(0x00) => {
+                        suspend_tx.unbounded_send(true).ok();
+                    }
+                    Some(0x01) => {
+                        suspend_tx.unbounded_send(false).ok();
+                    }
+                    _ => {}
+                },
+                GattEvent::Write(hogp::Token::MouseFeature, value) => {
+                    if let Some(feature) = value.first() {
+                        resolution_multiplier.set(*feature);
+                    }
+                }
+                GattEvent::Write(..) => {}
+            }
+        }
 
-        let kbtask = async move {
-            let (reply_tx, reply_rx) = oneshot::channel();
-            auth_channel.unbounded_send((addr.clone(), reply_tx))?;
+        anyhow::Result::<()>::Ok(())
+    }
+    .fuse();
+    pin_mut!(evtask);
 
-            reply_rx.await?;
-            log::debug!("Authenticated {}", addr);
-            authenticator.mark_authenticated();
+    let (authenticated_tx, authenticated_rx) = oneshot::channel::<()>();
+    let battask = async move {
+        // not to leak the level to the unauthenticated hosts.
+        if authenticated_rx.await.is_err() {
+            return;
+        }
+        // READ returns the level registered on accepted. notify if it changed since.
+        let mut notified = battery_level;
+        loop {
+            let level = *battery.borrow();
+            if level != notified {
+                if let Err(err) = batterynotify.write_all(&[level]).await {
+                    // may be connection terminated by remote host.
+                    log::info!("{}", err);
+                    break;
+                }
+                notified = level;
+            }
+            if battery.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+    .fuse();
+    pin_mut!(battask);
 
-            let mut input = input.use_stream().await?;
-            //let mut input = InputSourceWrapper::with(&mut input, grab)?;
+    let kbtask = async {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        auth_channel.unbounded_send((addr.clone(), reply_tx))?;
 
-            while let Some(event) = input.next().await {
-                match event {
-                    InputEvent::Keyboard(evt) => {
-                        if let Err(err) = evt.write_to(&mut kbdnotify).await {
-                            // may be connection terminated by remote host.
-                            log::info!("{}", err);
-                            break;
-                        }
+        reply_rx.await?;
+        log::debug!("Authenticated {}", addr);
+        authenticator.mark_authenticated();
+        authenticated_tx.send(()).ok();
+
+        let mut input = input.use_host_stream(addr.clone())?;
+        log::info!("host {} connected: {}", input.slot() + 1, addr);
+
+        let mut scroll = input::Scroll::default();
+        let mut suspended = false;
+        let mut nkro_active = *nkro.borrow();
+        loop {
+            let next = select! {
+                event = input.next().fuse() => Either::Left(event),
+                suspend = suspend_rx.select_next_some() => Either::Right(suspend),
+            };
+            let event = match next {
+                Either::Left(Some(event)) => event,
+                Either::Left(None) => break,
+                Either::Right(suspend) => {
+                    log::info!("host {} suspended: {}", input.slot() + 1, suspend);
+                    // stop notifications and release the grab.
+                    // (mgmt API has no way to update the parameters of the established connection.)
+                    suspended = suspend;
+                    input.set_suspended(suspend)?;
+                    continue;
+                }
+            };
+
+            if suspended {
+                match &event {
+                    // wake up the host by key press.
+                    InputEvent::Keyboard(evt) if !evt.keys().is_empty() => {
+                        log::info!("wake up host {}", input.slot() + 1);
+                        suspended = false;
+                        input.set_suspended(false)?;
                     }
-                    InputEvent::Mouse(evt) => {
-                        if let Err(err) = evt.write_to(&mut mousenotify).await {
-                            // may be connection terminated by remote host.
-                            log::info!("{}", err);
-                            break;
+                    _ => continue,
+                }
+            }
+
+            let boot = boot_protocol.get();
+            let result = match event {
+                InputEvent::Keyboard(evt) if boot => evt.write_to(&mut bootkbdnotify).await,
+                InputEvent::Keyboard(evt) => match &mut nkronotify {
+                    Some(nkronotify) => {
+                        let active = *nkro.borrow();
+                        // release the keys left in the report switched from.
+                        let released = KbStat::new();
+                        let result = match (nkro_active, active) {
+                            (true, false) => released.write_nkro_to(nkronotify).await,
+                            (false, true) => released.write_to(&mut kbdnotify).await,
+                            _ => Ok(()),
+                        };
+                        nkro_active = active;
+                        match result {
+                            Ok(()) if active => evt.write_nkro_to(nkronotify).await,
+                            Ok(()) => evt.write_to(&mut kbdnotify).await,
+                            err => err,
                         }
                     }
+                    None => evt.write_to(&mut kbdnotify).await,
+                },
+                InputEvent::Mouse(evt) if boot => evt.write_boot_to(&mut bootmousenotify).await,
+                InputEvent::Mouse(evt) => {
+                    scroll.set_resolution_multiplier(resolution_multiplier.get());
+                    evt.write_to(&mut mousenotify, &mut scroll).await
+                }
+                // no boot report.
+                InputEvent::Consumer(..) | InputEvent::Digitizer(..) if boot => Ok(()),
+                InputEvent::Consumer(evt) => evt.write_to(&mut consumernotify).await,
+                InputEvent::Digitizer(evt) => {
+                    if let Some(digitizernotify) = &mut digitizernotify {
+                        evt.write_to(digitizernotify).await
+                    } else {
+                        Ok(())
+                    }
                 }
+            };
+            if let Err(err) = result {
+                // may be connection terminated by remote host.
+                log::info!("{}", err);
+                break;
             }
+        }
+
+        anyhow::Result::<()>::Ok(())
+    }
+    .fuse();
+    pin_mut!(kbtask);
+
+    loop {
+        select! {
+            result = task => {
+                if let Err(err) = result {
+                    // may be connection terminated by remote host.
+                    log::info!("{}", err);
+                }
+                return Ok(());
+            },
 
-            anyhow::Result::<()>::Ok(())
+            result = kbtask => {
+                result?;
+            },
+
+            result = evtask => {
+                result?;
+            },
+
+            _ = battask => {},
         }
-        .fuse();
-        pin_mut!(kbtask);
+    }
+}
 
-        loop {
+async fn gatt_loop(
+    config: &Config,
+    battery: watch::Receiver<u8>,
+    nkro: watch::Receiver<bool>,
+    input: input::InputSource,
+    auth_channel: UnboundedSender<(Address, Sender<()>)>,
+) -> anyhow::Result<()> {
+    let mut server = Server::bind()?;
+    server.needs_bond_mitm()?;
+
+    log::info!("Start serving.");
+
+    let mut connections = FuturesUnordered::new();
+    loop {
+        // the gatt database has no read callback, so READ returns this level until disconnected.
+        // the hosts get the later changes by notification.
+        let level = *battery.borrow();
+        let accept = server.accept(hogp::new(config, level)).fuse();
+        pin_mut!(accept);
+
+        // keep the pending accept while serving the others.
+        let connection = loop {
             select! {
-                result = task => {
+                connection = accept => break connection?,
+                result = connections.select_next_some() => {
                     if let Err(err) = result {
-                        // may be connection terminated by remote host.
-                        log::info!("{}", err);
+                        log::warn!("{}", err);
                     }
-                    return Ok(());
-                },
-
-                result = kbtask => {
-                    result?;
                 },
             }
+        };
+
+        if let Some(connection) = connection {
+            connections.push(serve(
+                connection,
+                &config.hid,
+                level,
+                battery.clone(),
+                nkro.clone(),
+                input.clone(),
+                auth_channel.clone(),
+            ));
+        } else {
+            return Ok(());
         }
     }
-    Ok(())
 }
 
-#[allow(unused)]
-pub async fn run(var_file: PathBuf, device_id: u16, grab: bool) -> anyhow::Result<()> {
-    let io_capability = btmgmt::packet::IoCapability::KeyboardOnly;
+/// Serve on the controller until an unrecoverable error.
+async fn run_controller(
+    config: &Config,
+    var_file: &Path,
+    device_id: u16,
+    host_switch: Vec<Chord>,
+    control_socket: &Path,
+) -> anyhow::Result<()> {
+    let io_capability = config.pairing.io_capability.into();
 
     let store = Store::open(var_file).await?;
-    let gap_client = gap::setup(device_id, &store, io_capability).await?;
+    let gap_client = gap::setup(device_id, &store, io_capability, config).await?;
 
-    let (input, input_loop) = input::InputSource::new(grab)?;
+    let max_hosts = host_switch.len().max(1);
+    let monitor = Monitor::new();
+    let (pairing_tx, pairing_rx) = mpsc::unbounded();
+    let (input, input_loop) = input::InputSource::new(
+        config.input.clone(),
+        config.remap.clone(),
+        host_switch,
+        config
+            .advertising
+            .pairing_chord
+            .clone()
+            .map(|chord| (chord, pairing_tx.clone())),
+        monitor.clone(),
+    )?;
 
     let (auth_tx, auth_rx) = mpsc::unbounded();
-    let mut sig = sig::Sig::new()?;
+    let (battery_tx, battery_rx) = watch::channel(config.battery.level);
+    // switched by the control socket. (only with the NKRO report registered)
+    let (nkro_tx, nkro_rx) = watch::channel(config.hid.nkro);
+    let nkro_tx = if config.hid.nkro { Some(nkro_tx) } else { None };
+
+    // restarted subsystems reuse them.
+    let store = Mutex::new(store);
+    let auth_rx = Mutex::new(auth_rx);
+    let advertising_state = Mutex::new(AdvertisingState {
+        pairing_rx,
+        last_host: None,
+        lockout: Lockout::new(
+            config.pairing.max_failures,
+            config.pairing.max_total_failures,
+            Duration::from_secs(config.pairing.lockout),
+        ),
+    });
+    let input_loop = Mutex::new(input_loop);
+    let (gap_client, monitor, input, pairing_tx) = (&gap_client, &monitor, &input, &pairing_tx);
 
     log::info!("starting.");
     tokio::try_join!(
-        store_keys(device_id.into(), &gap_client, store, auth_rx),
-        passkey_input(device_id.into(), &gap_client, input.clone()),
-        advertising(device_id.into(), &gap_client, input.clone()),
-        gatt_loop(input, auth_tx),
-        sig.recv().map_err(Into::<anyhow::Error>::into),
-        input_loop,
+        supervise("key store", || async {
+            store_keys(
+                device_id.into(),
+                gap_client,
+                &mut *store.lock().await,
+                &mut *auth_rx.lock().await,
+                Duration::from_secs(config.pairing.bond_timeout),
+                monitor.clone(),
+            )
+            .await
+        }),
+        supervise("passkey input", || passkey_input(
+            device_id.into(),
+            gap_client,
+            input.clone(),
+            &config.pairing,
+            monitor.clone()
+        )),
+        supervise("advertising", || async {
+            advertising(
+                device_id.into(),
+                gap_client,
+                input.clone(),
+                config,
+                max_hosts,
+                &mut *advertising_state.lock().await,
+                monitor.clone(),
+            )
+            .await
+        }),
+        supervise("gatt server", || gatt_loop(
+            config,
+            battery_rx.clone(),
+            nkro_rx.clone(),
+            input.clone(),
+            auth_tx.clone()
+        )),
+        battery::watch(config.battery.clone(), battery_tx),
+        supervise("control socket", || control::serve(
+            control_socket.to_path_buf(),
+            device_id.into(),
+            gap_client,
+            input.clone(),
+            pairing_tx.clone(),
+            nkro_tx.as_ref(),
+            monitor.clone()
+        )),
+        supervise("input", || async { input_loop.lock().await.run().await }),
     )?;
     Ok(())
 }
+
+/// Serve on the controller whenever it is present.
+async fn follow_controller(
+    client: &MgmtClient,
+    controller: &Controller,
+    config: &Config,
+    var_file: &Path,
+    host_switch: &[Chord],
+    control_socket: &Path,
+) -> anyhow::Result<()> {
+    loop {
+        let device_id = controller.wait(client).await?;
+        log::info!("controller {} found at hci{}.", controller, device_id);
+        // retried with backoff while the controller is present.
+        let session = supervise("controller", || async move {
+            let result = run_controller(
+                config,
+                var_file,
+                device_id,
+                host_switch.to_vec(),
+                control_socket,
+            )
+            .await;
+            match result {
+                // failed by the removal. wait for the controller again.
+                Err(err) if controller.find(client).await?.is_none() => {
+                    log::warn!("controller {} lost: {}", controller, err);
+                    Ok(())
+                }
+                result => result,
+            }
+        });
+        select! {
+            result = Box::pin(session).fuse() => result?,
+            result = Box::pin(controller::removed(client, device_id)).fuse() => {
+                result?;
+                // dropping the session ungrabs the input and stops the GATT server.
+                log::warn!("controller {} removed. paused.", controller);
+            }
+        }
+    }
+}
+
+#[allow(unused)]
+pub async fn run(
+    config: Config,
+    var_file: PathBuf,
+    controller: Controller,
+    host_switch: Vec<Chord>,
+    control_socket: PathBuf,
+) -> anyhow::Result<()> {
+    let client = MgmtClient::open()?;
+    let mut sig = sig::Sig::new()?;
+
+    let serve = follow_controller(
+        &client,
+        &controller,
+        &config,
+        &var_file,
+        &host_switch,
+        &control_socket,
+    );
+    tokio::try_join!(serve, sig.recv().map_err(Into::<anyhow::Error>::into))?;
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! Reject the hosts failing to pair repeatedly.
+//!
+//! The lockout of an address only works for the public and static random addresses.
+//! Resolvable private addresses change on each connection, so the failures of all the
+//! addresses are also counted and the new hosts are rejected after too many of them.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bdaddr::Address;
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    since: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    fn new(now: Instant) -> Self {
+        Self {
+            count: 0,
+            since: now,
+            locked_until: None,
+        }
+    }
+
+    fn is_expired(&self, now: Instant, duration: Duration) -> bool {
+        match self.locked_until {
+            Some(until) => until <= now,
+            None => duration <= now.duration_since(self.since),
+        }
+    }
+
+    /// Returns true if locked out by this failure.
+    fn failed(&mut self, now: Instant, max_failures: u32, duration: Duration) -> bool {
+        self.count += 1;
+        if max_failures == 0 || self.count < max_failures || self.locked_until.is_some() {
+            return false;
+        }
+        self.locked_until = Some(now + duration);
+        true
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Lockout {
+    failures: HashMap<Address, Failures>,
+    /// Of all the addresses.
+    total: Option<Failures>,
+    max_failures: u32,
+    max_total_failures: u32,
+    duration: Duration,
+}
+
+impl Lockout {
+    /// Locks out for `duration` after `max_failures` within `duration`. (0 never locks out)
+    /// New hosts are rejected after `max_total_failures` of any addresses in the same way.
+    pub(crate) fn new(max_failures: u32, max_total_failures: u32, duration: Duration) -> Self {
+        Self {
+            failures: HashMap::new(),
+            total: None,
+            max_failures,
+            max_total_failures,
+            duration,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let duration = self.duration;
+        self.failures.retain(|_, f| !f.is_expired(now, duration));
+        if matches!(&self.total, Some(f) if f.is_expired(now, duration)) {
+            self.total = None;
+        }
+    }
+
+    pub(crate) fn is_locked(&mut self, addr: &Address, now: Instant) -> bool {
+        self.expire(now);
+        matches!(self.failures.get(addr), Some(f) if f.locked_until.is_some())
+    }
+
+    /// Pairing with the new hosts is locked out.
+    pub(crate) fn is_pairing_locked(&mut self, now: Instant) -> bool {
+        self.expire(now);
+        matches!(&self.total, Some(f) if f.locked_until.is_some())
+    }
+
+    /// Returns true if the address is locked out by this failure.
+    pub(crate) fn failed(&mut self, addr: &Address, now: Instant) -> bool {
+        self.expire(now);
+        let total = self.total.get_or_insert_with(|| Failures::new(now));
+        if total.failed(now, self.max_total_failures, self.duration) {
+            log::warn!(
+                "reject new hosts for {} seconds: too many failed pairings.",
+                self.duration.as_secs()
+            );
+        }
+        self.failures
+            .entry(addr.clone())
+            .or_insert_with(|| Failures::new(now))
+            .failed(now, self.max_failures, self.duration)
+    }
+
+    pub(crate) fn succeeded(&mut self, addr: &Address) {
+        self.failures.remove(addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Address {
+        s.parse::<bdaddr::BdAddr>().unwrap().to_le_random_addr()
+    }
+
+    #[test]
+    fn test_threshold() {
+        let now = Instant::now();
+        let mut lockout = Lockout::new(3, 0, Duration::from_secs(300));
+        let a = addr("C0:00:00:00:00:01");
+        assert!(!lockout.failed(&a, now));
+        assert!(!lockout.failed(&a, now));
+        assert!(!lockout.is_locked(&a, now));
+        assert!(lockout.failed(&a, now));
+        assert!(lockout.is_locked(&a, now));
+        // already locked out.
+        assert!(!lockout.failed(&a, now));
+        assert!(!lockout.is_locked(&addr("C0:00:00:00:00:02"), now));
+        assert!(!lockout.is_pairing_locked(now));
+    }
+
+    #[test]
+    fn test_expire() {
+        let now = Instant::now();
+        let duration = Duration::from_secs(300);
+        let mut lockout = Lockout::new(2, 0, duration);
+        let a = addr("C0:00:00:00:00:01");
+        lockout.failed(&a, now);
+        // the failures before `duration` are forgotten.
+        assert!(!lockout.failed(&a, now + duration));
+        assert!(lockout.failed(&a, now + duration * 2 - Duration::from_secs(1)));
+        assert!(lockout.is_locked(&a, now + duration * 3 - Duration::from_secs(2)));
+        assert!(!lockout.is_locked(&a, now + duration * 3));
+    }
+
+    #[test]
+    fn test_succeeded() {
+        let now = Instant::now();
+        let mut lockout = Lockout::new(2, 0, Duration::from_secs(300));
+        let a = addr("C0:00:00:00:00:01");
+        lockout.failed(&a, now);
+        lockout.succeeded(&a);
+        assert!(!lockout.failed(&a, now));
+    }
+
+    #[test]
+    fn test_total() {
+        let now = Instant::now();
+        let duration = Duration::from_secs(300);
+        let mut lockout = Lockout::new(0, 3, duration);
+        for i in 1..=3 {
+            let a = addr(&format!("40:00:00:00:00:0{}", i));
+            assert!(!lockout.is_pairing_locked(now));
+            assert!(!lockout.failed(&a, now));
+            assert!(!lockout.is_locked(&a, now));
+        }
+        assert!(lockout.is_pairing_locked(now));
+        assert!(lockout.is_pairing_locked(now + duration - Duration::from_secs(1)));
+        assert!(!lockout.is_pairing_locked(now + duration));
+    }
+}
@@ -1,10 +1,16 @@
 #![warn(clippy::all)]
+use std::io;
 use std::path::PathBuf;
 
-use clap::Parser;
+use btknmle::config::{Config, GrabPolicy};
+use btknmle::control::Request;
+use clap::{Parser, Subcommand};
 
 #[derive(Debug, Parser)]
 struct Opts {
+    #[clap(short = 'c', long, env = "BTKNMLE_CONFIG")]
+    config: Option<PathBuf>,
+
     #[clap(
         short = 'f',
         long,
@@ -13,11 +19,43 @@ struct Opts {
     )]
     var_file: PathBuf,
 
+    /// Controller index (`0` or `hci0`) or public address. Waits until it is added.
     #[clap(short = 'd', long, env = "BTKNMLE_DEVID", default_value = "0")]
-    device_id: u16,
+    device_id: btknmle::Controller,
+
+    /// never / while-connected / always. `--grab` alone means while-connected.
+    /// (overrides the config file)
+    #[clap(
+        long,
+        env = "BTKNMLE_GRAB",
+        min_values = 0,
+        max_values = 1,
+        default_missing_value = "while-connected"
+    )]
+    grab: Option<GrabPolicy>,
+
+    /// udev seat of the input devices. (overrides the config file)
+    #[clap(long, env = "BTKNMLE_SEAT")]
+    seat: Option<String>,
 
-    #[clap(long, env = "BTKNMLE_GRAB")]
-    grab: bool,
+    /// Key chords to switch the active host. The number of chords is the number of hosts.
+    ///
+    /// Avoid ctrl+alt+F1.. switching the local virtual terminal while not grabbed.
+    #[clap(
+        long,
+        env = "BTKNMLE_HOST_SWITCH",
+        use_delimiter = true,
+        default_value = "ctrl+shift+f1,ctrl+shift+f2,ctrl+shift+f3,ctrl+shift+f4"
+    )]
+    host_switch: Vec<btknmle::Chord>,
+
+    /// Control socket of the daemon.
+    #[clap(
+        long,
+        env = "BTKNMLE_CONTROL_SOCKET",
+        default_value = "/run/btknmle/control.sock"
+    )]
+    control_socket: PathBuf,
 
     #[clap(short = 'v', long, parse(from_occurrences), conflicts_with_all = &["debug", "trace"])]
     verbosity: usize,
@@ -27,17 +65,46 @@ struct Opts {
 
     #[clap(short = 'T', long, env = "BTKNMLE_TRACE", conflicts_with_all = &["debug", "verbosity"])]
     trace: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage bonded hosts.
+    #[clap(subcommand)]
+    Bonds(btknmle::bonds::Command),
+
+    /// Type text on the active host through the running daemon.
+    Type {
+        /// Read from stdin if omitted.
+        text: Option<String>,
+
+        /// Keyboard layout of the host. (us / jis / de)
+        #[clap(short, long, default_value = "us")]
+        layout: btknmle::Layout,
+
+        /// Milliseconds after each key press and release.
+        #[clap(long, default_value = "10")]
+        delay: u64,
+    },
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> anyhow::Result<()> {
     let Opts {
+        config,
         var_file,
         device_id,
         grab,
+        seat,
+        host_switch,
+        control_socket,
         mut verbosity,
         debug,
         trace,
+        command,
     } = Opts::parse();
 
     if trace {
@@ -53,5 +120,41 @@ async fn main() -> anyhow::Result<()> {
         _ => log::Level::Trace,
     })?;
 
-    btknmle::run(var_file, device_id, grab).await
+    match command {
+        Some(Command::Bonds(command)) => {
+            return btknmle::bonds::run(var_file, device_id, control_socket, command).await;
+        }
+        Some(Command::Type {
+            text,
+            layout,
+            delay,
+        }) => {
+            let text = match text {
+                Some(text) => text,
+                None => io::read_to_string(io::stdin())?,
+            };
+            let request = Request::Type {
+                text,
+                layout,
+                delay,
+            };
+            btknmle::control::request(control_socket, &request).await?;
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let mut config = if let Some(config) = config {
+        Config::load(config)?
+    } else {
+        Config::default()
+    };
+    if let Some(seat) = seat {
+        config.input.seat = seat;
+    }
+    if let Some(grab) = grab {
+        config.input.grab = grab;
+    }
+
+    btknmle::run(config, var_file, device_id, host_switch, control_socket).await
 }
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use bdaddr::Address;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Connected host. `host` is the number of the host slot. (1-based)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Host {
+    pub host: usize,
+    pub address: String,
+}
+
+/// Shown while pairing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Passkey {
+    pub address: String,
+    /// Six digits.
+    pub passkey: String,
+    /// Confirm it matches the host by Y or N on the keyboard. Otherwise type it on the host.
+    pub confirm: bool,
+}
+
+impl Passkey {
+    pub(crate) fn new(address: &Address, passkey: u32, confirm: bool) -> Self {
+        Self {
+            address: address.to_string(),
+            passkey: format!("{:06}", passkey),
+            confirm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub hosts: Vec<Host>,
+    /// Host receiving the input.
+    pub active_host: Option<usize>,
+    pub advertising: bool,
+    pub grabbed: bool,
+    /// Identity addresses of the bonded hosts.
+    pub bonds: Vec<String>,
+    pub passkey: Option<Passkey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    HostConnected(Host),
+    HostDisconnected(Host),
+    ActiveHost(Option<usize>),
+    Advertising(bool),
+    Grabbed(bool),
+    Bonds(Vec<String>),
+    Passkey(Option<Passkey>),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// slot -> address
+    hosts: BTreeMap<usize, Address>,
+    active: Option<usize>,
+    advertising: bool,
+    grabbed: bool,
+    bonds: Vec<Address>,
+    passkey: Option<Passkey>,
+}
+
+fn host(slot: usize, address: &Address) -> Host {
+    Host {
+        host: slot + 1,
+        address: address.to_string(),
+    }
+}
+
+fn bonds(addrs: &[Address]) -> Vec<String> {
+    addrs.iter().map(ToString::to_string).collect()
+}
+
+/// Daemon state shared with the control socket.
+#[derive(Debug, Clone)]
+pub(crate) struct Monitor {
+    state: Arc<Mutex<State>>,
+    events: broadcast::Sender<Event>,
+}
+
+impl Monitor {
+    pub(crate) fn new() -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            state: Default::default(),
+            events,
+        }
+    }
+
+    /// Emits the event if returned.
+    fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut State) -> Option<Event>,
+    {
+        let event = f(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()));
+        if let Some(event) = event {
+            // no subscribers.
+            self.events.send(event).ok();
+        }
+    }
+
+    fn read<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&State) -> R,
+    {
+        f(&self.state.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub(crate) fn status(&self) -> Status {
+        self.read(|state| Status {
+            hosts: state.hosts.iter().map(|(s, a)| host(*s, a)).collect(),
+            active_host: state.active.map(|s| s + 1),
+            advertising: state.advertising,
+            grabbed: state.grabbed,
+            bonds: bonds(&state.bonds),
+            passkey: state.passkey.clone(),
+        })
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    pub(crate) fn host_address(&self, slot: usize) -> Option<Address> {
+        self.read(|state| state.hosts.get(&slot).cloned())
+    }
+
+    pub(crate) fn bonds(&self) -> Vec<Address> {
+        self.read(|state| state.bonds.clone())
+    }
+
+    pub(crate) fn host_connected(&self, slot: usize, address: Address) {
+        self.update(|state| {
+            let event = Event::HostConnected(host(slot, &address));
+            state.hosts.insert(slot, address);
+            Some(event)
+        })
+    }
+
+    pub(crate) fn host_disconnected(&self, slot: usize) {
+        self.update(|state| {
+            let address = state.hosts.remove(&slot)?;
+            Some(Event::HostDisconnected(host(slot, &address)))
+        })
+    }
+
+    pub(crate) fn set_active_host(&self, slot: Option<usize>) {
+        self.update(|state| {
+            if state.active == slot {
+                return None;
+            }
+            state.active = slot;
+            Some(Event::ActiveHost(slot.map(|s| s + 1)))
+        })
+    }
+
+    pub(crate) fn set_advertising(&self, advertising: bool) {
+        self.update(|state| {
+            if state.advertising == advertising {
+                return None;
+            }
+            state.advertising = advertising;
+            Some(Event::Advertising(advertising))
+        })
+    }
+
+    pub(crate) fn set_grabbed(&self, grabbed: bool) {
+        self.update(|state| {
+            if state.grabbed == grabbed {
+                return None;
+            }
+            state.grabbed = grabbed;
+            Some(Event::Grabbed(grabbed))
+        })
+    }
+
+    pub(crate) fn set_bonds(&self, addrs: Vec<Address>) {
+        self.update(|state| {
+            if state.bonds == addrs {
+                return None;
+            }
+            state.bonds = addrs;
+            Some(Event::Bonds(bonds(&state.bonds)))
+        })
+    }
+
+    /// Clear the passkey shown for the host.
+    pub(crate) fn clear_passkey_of(&self, address: &Address) {
+        self.update(|state| {
+            if state.passkey.as_ref()?.address != address.to_string() {
+                return None;
+            }
+            state.passkey = None;
+            Some(Event::Passkey(None))
+        })
+    }
+
+    pub(crate) fn set_passkey(&self, passkey: Option<Passkey>) {
+        self.update(|state| {
+            if state.passkey == passkey {
+                return None;
+            }
+            state.passkey = passkey.clone();
+            Some(Event::Passkey(passkey))
+        })
+    }
+}
@@ -0,0 +1,287 @@
+//! Passkey input on the local keyboard.
+
+use std::fmt;
+use std::time::Duration;
+
+use futures_util::{select, FutureExt as _};
+
+use crate::hid::KeyboardUsageId;
+use crate::input::kbstat::Leds;
+use crate::input::{InputEvent, InputSource, InputStream};
+
+const PASSKEY_DIGITS: usize = 6;
+
+const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    Continue,
+    Done(u32),
+    Cancel,
+}
+
+/// Entered digits. Displayed as `***___`.
+#[derive(Debug, Default)]
+struct Entry {
+    digits: Vec<u32>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for n in 0..PASSKEY_DIGITS {
+            f.write_str(if n < self.digits.len() { "*" } else { "_" })?;
+        }
+        Ok(())
+    }
+}
+
+fn digit(key: &KeyboardUsageId) -> Option<u32> {
+    use KeyboardUsageId::*;
+
+    Some(match key {
+        KEY_0 | KEY_KP0 => 0,
+        KEY_1 | KEY_KP1 => 1,
+        KEY_2 | KEY_KP2 => 2,
+        KEY_3 | KEY_KP3 => 3,
+        KEY_4 | KEY_KP4 => 4,
+        KEY_5 | KEY_KP5 => 5,
+        KEY_6 | KEY_KP6 => 6,
+        KEY_7 | KEY_KP7 => 7,
+        KEY_8 | KEY_KP8 => 8,
+        KEY_9 | KEY_KP9 => 9,
+        _ => return None,
+    })
+}
+
+impl Entry {
+    fn press(&mut self, key: &KeyboardUsageId) -> Step {
+        use KeyboardUsageId::*;
+
+        match key {
+            KEY_ESC => return Step::Cancel,
+            KEY_ENTER | KEY_KPENTER if self.digits.len() == PASSKEY_DIGITS => {
+                return Step::Done(self.digits.iter().fold(0, |acc, d| acc * 10 + d));
+            }
+            KEY_ENTER | KEY_KPENTER => {
+                log::warn!("passkey must be {} digits.", PASSKEY_DIGITS);
+                return Step::Continue;
+            }
+            // Backspace
+            KEY_DELETE => {
+                self.digits.pop();
+            }
+            key => match digit(key) {
+                Some(d) if self.digits.len() < PASSKEY_DIGITS => self.digits.push(d),
+                Some(..) => {
+                    log::warn!("passkey must be {} digits.", PASSKEY_DIGITS);
+                    return Step::Continue;
+                }
+                None => return Step::Continue,
+            },
+        }
+        log::info!("passkey: {}", self);
+        Step::Continue
+    }
+}
+
+/// Restores the host LEDs on drop. (also on aborted)
+struct LocalLeds<'a>(&'a InputSource);
+
+impl Drop for LocalLeds<'_> {
+    fn drop(&mut self) {
+        self.0.set_local_leds(None).ok();
+    }
+}
+
+/// Feed the newly pressed keys to `f` until it returns `Some` while blinking the keyboard LEDs.
+async fn read_keys<F, R>(
+    source: &InputSource,
+    input: &mut InputStream<'_>,
+    mut f: F,
+) -> anyhow::Result<R>
+where
+    F: FnMut(&KeyboardUsageId) -> Option<R>,
+{
+    let mut pressed = Vec::<KeyboardUsageId>::new();
+    let mut blink = tokio::time::interval(BLINK_INTERVAL);
+    let mut lit = false;
+
+    loop {
+        select! {
+            event = input.next().fuse() => {
+                let kbstat = match event {
+                    Some(InputEvent::Keyboard(kbstat)) => kbstat,
+                    Some(..) => continue,
+                    None => anyhow::bail!("input closed."),
+                };
+                let keys = kbstat.keys().to_vec();
+                for key in keys.iter().filter(|k| !pressed.contains(k)) {
+                    if let Some(result) = f(key) {
+                        return Ok(result);
+                    }
+                }
+                pressed = keys;
+            }
+
+            _ = blink.tick().fuse() => {
+                lit = !lit;
+                let leds = if lit {
+                    Leds::NUM_LOCK | Leds::CAPS_LOCK | Leds::SCROLL_LOCK
+                } else {
+                    Leds::empty()
+                };
+                source.set_local_leds(Some(leds))?;
+            }
+        }
+    }
+}
+
+/// Returns `None` if timed out.
+async fn with_timeout<F, R>(
+    source: &InputSource,
+    timeout: Duration,
+    f: F,
+) -> anyhow::Result<Option<R>>
+where
+    F: FnMut(&KeyboardUsageId) -> Option<R>,
+{
+    let mut input = source.use_stream().await?;
+    let _leds = LocalLeds(source);
+    let result = tokio::time::timeout(timeout, read_keys(source, &mut input, f)).await;
+    match result {
+        Ok(result) => result.map(Some),
+        Err(..) => Ok(None),
+    }
+}
+
+/// Read the passkey while blinking the keyboard LEDs.
+///
+/// Enter submits six digits, Backspace deletes the last digit and Esc cancels.
+/// Returns `None` if cancelled or timed out.
+pub(crate) async fn input_passkey(
+    source: &InputSource,
+    timeout: Duration,
+) -> anyhow::Result<Option<u32>> {
+    let mut entry = Entry::default();
+    log::info!("passkey: {}", entry);
+    let result = with_timeout(source, timeout, |key| match entry.press(key) {
+        Step::Continue => None,
+        Step::Done(passkey) => Some(Some(passkey)),
+        Step::Cancel => Some(None),
+    })
+    .await?;
+    match result {
+        Some(Some(passkey)) => Ok(Some(passkey)),
+        Some(None) => {
+            log::info!("passkey input cancelled.");
+            Ok(None)
+        }
+        None => {
+            log::warn!("passkey input timed out.");
+            Ok(None)
+        }
+    }
+}
+
+/// Wait Y (or Enter) to accept and N (or Esc) to reject. Timeout rejects.
+pub(crate) async fn confirm(source: &InputSource, timeout: Duration) -> anyhow::Result<bool> {
+    use KeyboardUsageId::*;
+
+    let result = with_timeout(source, timeout, |key| match key {
+        KEY_Y | KEY_ENTER | KEY_KPENTER => Some(true),
+        KEY_N | KEY_ESC => Some(false),
+        _ => None,
+    })
+    .await?;
+    if result.is_none() {
+        log::warn!("confirmation timed out.");
+    }
+    Ok(result.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use KeyboardUsageId::*;
+
+    fn press(entry: &mut Entry, keys: &[KeyboardUsageId]) -> Step {
+        let mut step = Step::Continue;
+        for key in keys {
+            step = entry.press(key);
+        }
+        step
+    }
+
+    #[test]
+    fn test_enter() {
+        let mut entry = Entry::default();
+        let step = press(
+            &mut entry,
+            &[KEY_0, KEY_1, KEY_2, KEY_3, KEY_4, KEY_5, KEY_ENTER],
+        );
+        assert_eq!(step, Step::Done(12345));
+    }
+
+    #[test]
+    fn test_numpad() {
+        let mut entry = Entry::default();
+        let step = press(
+            &mut entry,
+            &[
+                KEY_KP9,
+                KEY_KP8,
+                KEY_7,
+                KEY_KP6,
+                KEY_KP5,
+                KEY_KP0,
+                KEY_KPENTER,
+            ],
+        );
+        assert_eq!(step, Step::Done(987650));
+    }
+
+    #[test]
+    fn test_backspace() {
+        let mut entry = Entry::default();
+        let step = press(
+            &mut entry,
+            &[
+                KEY_1, KEY_2, KEY_9, KEY_DELETE, KEY_3, KEY_4, KEY_5, KEY_6, KEY_ENTER,
+            ],
+        );
+        assert_eq!(step, Step::Done(123456));
+
+        // nothing to delete.
+        let mut entry = Entry::default();
+        assert_eq!(press(&mut entry, &[KEY_DELETE, KEY_DELETE]), Step::Continue);
+        assert_eq!(entry.to_string(), "______");
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut entry = Entry::default();
+        let step = press(
+            &mut entry,
+            &[KEY_1, KEY_2, KEY_3, KEY_4, KEY_5, KEY_6, KEY_7, KEY_8],
+        );
+        assert_eq!(step, Step::Continue);
+        assert_eq!(entry.to_string(), "******");
+        assert_eq!(press(&mut entry, &[KEY_ENTER]), Step::Done(123456));
+    }
+
+    #[test]
+    fn test_short() {
+        let mut entry = Entry::default();
+        assert_eq!(
+            press(&mut entry, &[KEY_1, KEY_2, KEY_3, KEY_ENTER]),
+            Step::Continue
+        );
+        assert_eq!(entry.to_string(), "***___");
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut entry = Entry::default();
+        assert_eq!(press(&mut entry, &[KEY_1, KEY_A, KEY_ESC]), Step::Cancel);
+    }
+}
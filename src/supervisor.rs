@@ -0,0 +1,50 @@
+//! Restart the failed subsystems.
+
+use std::future::Future;
+use std::io;
+use std::time::{Duration, Instant};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// The backoff is reset after running longer than it.
+const STABLE: Duration = Duration::from_secs(60);
+
+/// Restarting never recovers from them.
+fn is_fatal(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        matches!(
+            e.downcast_ref::<io::Error>(),
+            Some(e) if e.kind() == io::ErrorKind::PermissionDenied
+        )
+    })
+}
+
+/// Run the subsystem again with exponential backoff whenever it fails.
+///
+/// Finishing without an error is not restarted. (e.g. its channel is closed)
+/// Returns the fatal errors.
+pub(crate) async fn supervise<F, Fut>(name: &str, mut f: F) -> anyhow::Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let started = Instant::now();
+        match f().await {
+            Ok(()) => {
+                log::info!("{} finished.", name);
+                return Ok(());
+            }
+            Err(err) if is_fatal(&err) => return Err(err.context(format!("{} failed", name))),
+            Err(err) => log::error!("{} failed: {:?}", name, err),
+        }
+
+        if started.elapsed() > STABLE {
+            backoff = INITIAL_BACKOFF;
+        }
+        log::info!("restart {} in {:?}.", name, backoff);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}